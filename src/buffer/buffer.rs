@@ -1,253 +1,1745 @@
+use std::borrow::Cow;
 use std::fmt::Display;
-use crate::buffer::token::{Token, TokenKind};
+use std::path::Path;
+use ropey::Rope;
+use crate::buffer::token::{get_kind, Token, TokenKind};
 use crate::buffer::lexer::Lexer;
-use crate::{handler::Mode, Motion, Cmd, Action, Dir, Dest};
+use crate::buffer::file::FileInfo;
+use crate::buffer::syntax::{Highlighter, Language, Span};
+#[cfg(feature = "tree-sitter")]
+use crate::buffer::tree::SyntaxTree;
+use crate::{handler::Mode, Motion, Target, Action, Dir, Dest, Seek, Object, Visual};
+use crate::history::{Delta, StateHistory};
+use crate::search::Pattern;
+
+// Rows a search looks at before moving on to the next ones
+const SEARCH_ROWS: usize = 256;
 
 
 //------------------------------------------------------
 // Buffer, Seeker & SeekTarget
 //------------------------------------------------------
+// `cursor` is a char index into `content`. In Edit mode it is the char
+// under the block cursor, in Insert mode the point where text goes in.
 pub struct Buffer {
-  pub content: String,
+  pub content: Rope,
   pub cursor: usize,
-  token_list: Vec<Token>
+  // Other end of the selection in Visual mode
+  pub anchor: Option<usize>,
+  // Column vertical motions try to land on, like vim's curswant
+  curswant: usize,
+  pub file: FileInfo,
+  dirty: bool,
+  history: StateHistory,
+  // Content at the last undo step and the stretch changed since, which
+  // makes the next step
+  committed: Rope,
+  uncommitted: Option<Edit>,
+  token_list: Vec<Token>,
+  syntax: Highlighter,
+  #[cfg(feature = "tree-sitter")]
+  tree: Option<SyntaxTree>,
+  // Stretch changed since the token list and highlighting were updated
+  edited: Option<Edit>,
+  // Edits since positions kept outside the buffer were last moved
+  moves: Vec<Edit>,
+  // Closed folds, as the chars starting their first and last rows so
+  // edits can move them
+  folds: Vec<(usize, usize)>,
+  block: Option<BlockInsert>,
+  // First and last row of the last selection, the marks '< and '>
+  last_visual: Option<(usize, usize)>,
 }
 
 impl Buffer {
   pub fn new(s: &str) -> Self {
     let token_list = Lexer::from(s).collect();
-    Buffer { 
-      content: s.to_string(),
+    let content = Rope::from_str(s);
+    let syntax = Highlighter::new(Language::Plain, &content);
+    Buffer {
+      history: StateHistory::new(),
+      committed: content.clone(),
+      uncommitted: None,
+      content,
       cursor: 0,
-      token_list
+      anchor: None,
+      curswant: 0,
+      file: FileInfo::new(None),
+      dirty: false,
+      token_list,
+      syntax,
+      #[cfg(feature = "tree-sitter")]
+      tree: None,
+      edited: None,
+      moves: Vec::new(),
+      folds: Vec::new(),
+      block: None,
+      last_visual: None,
     }
   }
 
+  pub fn open(path: &Path) -> Result<Self, String> {
+    let (file, text) = FileInfo::read(path)?;
+    let mut buffer = Buffer::new(&text);
+    buffer.file = file;
+    buffer.set_language(Language::from_path(path));
+    Ok(buffer)
+  }
+
+  // Writes to `path`, or back to where the buffer came from
+  pub fn save(&mut self, path: Option<&Path>) -> Result<String, String> {
+    let target = match (path, &self.file.path) {
+      (Some(path), _) => path.to_path_buf(),
+      (None, Some(own)) => own.clone(),
+      (None, None) => return Err("No file name".to_string()),
+    };
+    let bytes = self.file.write(&self.content, &target)?;
+    if self.file.path.is_none() {
+      self.file.path = Some(target.clone());
+      if self.language() == Language::Plain {
+        self.set_language(Language::from_path(&target));
+      }
+    }
+    if self.file.path.as_ref() == Some(&target) {
+      self.commit();
+      self.history.mark_saved();
+      self.dirty = false;
+    }
+    Ok(format!("\"{}\" {}L, {}B written", target.display(), self.height(), bytes))
+  }
+
+  pub fn is_dirty(&self) -> bool {
+    self.dirty
+  }
+
+  // Closes the current change into one undo step, holding the text of
+  // the changed stretch before and after
+  pub fn commit(&mut self) {
+    let Some(edit) = self.uncommitted.take() else { return };
+    self.history.push(Delta {
+      from: edit.from,
+      removed: self.committed.slice(edit.from..edit.old_to).to_string(),
+      inserted: self.content.slice(edit.from..edit.new_to).to_string(),
+    });
+    self.committed = self.content.clone();
+  }
+
+  pub fn undo(&mut self) {
+    self.commit();
+    let steps = self.history.previous();
+    self.step(steps)
+  }
+
+  pub fn redo(&mut self) {
+    self.commit();
+    let steps = self.history.next();
+    self.step(steps)
+  }
+
+  pub fn earlier(&mut self) {
+    self.commit();
+    let steps = self.history.earlier();
+    self.step(steps)
+  }
+
+  pub fn later(&mut self) {
+    self.commit();
+    let steps = self.history.later();
+    self.step(steps)
+  }
+
+  pub fn history(&self) -> &StateHistory {
+    &self.history
+  }
+
   pub fn row(&self) -> usize {
-    self.content
-      .char_indices()
-      .take_while(|(idx, _)| *idx <= self.cursor)
-      .filter(|(_, c)| *c == '\n')
-      .count()
-  }
-
-  pub fn apply_motion(&mut self, m: Motion, mode: Mode) {
-    println!("byte: {}, cursor: {}", self.byte_index(), self.cursor);
-    let verb = match m.buf[0] {
-      Cmd::Verb(v) => v,
-      _ => panic!("Motion has no verb\n{}", m)
+    self.content.char_to_line(self.cursor)
+  }
+
+  pub fn col(&self) -> usize {
+    self.cursor - self.content.line_to_char(self.row())
+  }
+
+  // Returns the text an operator took out of, or copied from, the buffer
+  pub fn apply_motion(&mut self, m: Motion, mode: Mode) -> Option<Yanked> {
+    let yanked = match m.target {
+      Target::Dir(dir) => self.apply_w_dir(m.verb, m.count(), dir),
+      Target::Dest(dest) => self.apply_no_dir(m.verb, dest, m.count()),
+      Target::Object(object, inner) => self.apply_object(m.verb, object, inner, m.count()),
+      Target::Selection(visual) => self.apply_selection(m.verb, visual),
     };
-    match m.buf[1] {
-      Cmd::By(n) => self.apply_w_dir(verb, n, m.buf[2].unwrap_dir()),
-      Cmd::ToDest(dest) => self.apply_no_dir(verb, dest),
-      _ => panic!("Motion[1] is empty\n{}", m),
+    match mode {
+      Mode::Edit => self.jump_back_if_end(),
+      // $ selects up to and including the line break
+      Mode::Visual(_) if m.target != Target::Dest(Dest::Endl) => self.jump_back_if_end(),
+      _ => {},
+    }
+    match (m.verb, m.target) {
+      (_, Target::Dir(Dir::U | Dir::D)) => {},
+      (Action::Move, Target::Dest(Dest::Endl)) => self.curswant = usize::MAX,
+      _ => self.curswant = self.col(),
+    }
+    yanked
+  }
+
+  fn apply_w_dir(&mut self, verb: Action, by: usize, dir: Dir) -> Option<Yanked> {
+    let row = self.row();
+    match (verb, dir) {
+      (Action::Move, _) => {
+        self.move_cursor(by, dir);
+        Option::None
+      },
+      (Action::Join, _) => Option::None,
+      (_, Dir::L) => self.take(verb, self.cursor.saturating_sub(by), self.cursor),
+      (_, Dir::R) => self.take(verb, self.cursor, (self.cursor + by).min(self.end_of_row())),
+      (_, Dir::U) => self.take_rows(verb, row.saturating_sub(by), row),
+      (_, Dir::D) => self.take_rows(verb, row, row + by),
+    }
+  }
+
+  fn apply_no_dir(&mut self, verb: Action, dest: Dest, n: usize) -> Option<Yanked> {
+    if let Dest::Seek(seek, forwards) = dest {
+      return self.apply_seek(verb, seek, forwards, n)
+    }
+    // % is inclusive
+    if dest == Dest::Partner {
+      let to = self.partner()?;
+      return match verb {
+        Action::Move => {
+          self.cursor = to;
+          Option::None
+        },
+        Action::Join => Option::None,
+        _ => self.take(verb, self.cursor.min(to), self.cursor.max(to) + 1),
+      }
+    }
+    let row = self.row();
+    let last = self.height() - 1;
+    // Row the motion ends on, for linewise operators
+    let target = match dest {
+      Dest::Line | Dest::TxtStart | Dest::Endl => (row + n - 1).min(last),
+      Dest::Row(n) => n.saturating_sub(1).min(last),
+      Dest::LastRow => last,
+      Dest::LineStart | Dest::FirstNonBlank | Dest::Seek(..) | Dest::Partner => row,
     };
-    if mode == Mode::Edit {
-      self.jump_back_if_end();
+    match (verb, dest) {
+      (Action::Move, Dest::LineStart) => self.cursor = self.start_of_row(),
+      (Action::Move, Dest::FirstNonBlank) => self.goto_row(row),
+      (Action::Move, Dest::Endl) => {
+        self.cursor = self.content.line_to_char(target) + self.row_len(target);
+      },
+      (Action::Move, Dest::Line) => {},
+      (Action::Move, _) => self.goto_row(target),
+      (Action::Join, _) => self.join(n.max(2) - 1),
+      (_, Dest::LineStart) => return self.take(verb, self.start_of_row(), self.cursor),
+      (_, Dest::FirstNonBlank) => {
+        let indent = self.nth(row).chars().take_while(|c| c.is_whitespace()).count();
+        let text = self.start_of_row() + indent;
+        let (from, to) = (text.min(self.cursor), text.max(self.cursor));
+        return self.take(verb, from, to)
+      },
+      (_, Dest::Endl) => {
+        let end = self.content.line_to_char(target) + self.row_len(target);
+        return self.take(verb, self.cursor, end)
+      },
+      (_, _) => return self.take_rows(verb, row, target),
     }
+    Option::None
   }
 
-  fn apply_w_dir(&mut self, verb: Action, by: usize, dir: Dir) {
+  // Word, sentence and paragraph motions. Operators on them are
+  // charwise and exclusive, except e, E, ge and gE which include the
+  // char they land on.
+  fn apply_seek(&mut self, verb: Action, seek: Seek, forwards: bool, n: usize) -> Option<Yanked> {
+    let len = self.content.len_chars();
+    let on_blank = self.content.get_char(self.cursor).is_none_or(char::is_whitespace);
+    // cw and cW stop at the end of the word like ce, not at the next one
+    let seek = match (verb, seek, forwards) {
+      (Action::Change, Seek::Word, true) if !on_blank => Seek::WordEnd,
+      (Action::Change, Seek::BigWord, true) if !on_blank => Seek::BigWordEnd,
+      _ => seek,
+    };
+    let mut last = self.cursor;
+    let mut to = self.cursor;
+    if let Seek::Char(_) | Seek::Till(..) = seek {
+      // The whole count has to be found on the row or nothing happens
+      to = self.find_in_row(self.cursor, seek, forwards, n)?;
+    } else {
+      for i in 0..n {
+        last = to;
+        to = match (verb, seek) {
+          // The word under the cursor counts as the first one
+          (Action::Change, Seek::WordEnd | Seek::BigWordEnd) if forwards && !on_blank => {
+            let from = if i == 0 { to } else { to + 1 };
+            self.words(seek == Seek::BigWordEnd)
+              .into_iter()
+              .find(|word| word.1 >= from)
+              .map_or(len.saturating_sub(1), |word| word.1)
+          },
+          _ => self.seek_from(to, seek, forwards),
+        };
+      }
+    }
+    if verb == Action::Move {
+      self.cursor = to.min(len.saturating_sub(1));
+      return Option::None
+    }
+    if verb == Action::Join {
+      return Option::None
+    }
+    let (mut from, mut to) = match (seek, forwards) {
+      (Seek::WordEnd | Seek::BigWordEnd, true)  => (self.cursor, (to + 1).min(len)),
+      (Seek::WordEnd | Seek::BigWordEnd, false) => (to, (self.cursor + 1).min(len)),
+      (Seek::Char(_) | Seek::Till(..), true) => (self.cursor, (to + 1).min(len)),
+      (_, true)  => (self.cursor, to),
+      (_, false) => (to, self.cursor),
+    };
+    // dw on the last word of a row stops at the end of that row
+    if matches!(seek, Seek::Word | Seek::BigWord) && forwards
+      && self.content.char_to_line(to) > self.content.char_to_line(last) {
+      let row = self.content.char_to_line(last);
+      to = to.min(self.content.line_to_char(row) + self.row_len(row)).max(from);
+    }
+    let exclusive = !matches!(seek, Seek::WordEnd | Seek::BigWordEnd);
+    let (first, end) = (self.content.char_to_line(from), self.content.char_to_line(to));
+    // An exclusive motion ending in the first column of a later row
+    // ends on the row above instead, and takes whole rows when it also
+    // starts before the text of its row
+    if exclusive && end > first && to == self.content.line_to_char(end) {
+      to -= 1;
+      let indent = self.nth(first).chars().take_while(|c| c.is_whitespace()).count();
+      if from <= self.content.line_to_char(first) + indent {
+        return self.take_rows(verb, first, end - 1)
+      }
+    }
+    from = from.min(to);
+    self.take(verb, from, to)
+  }
+
+  fn apply_object(&mut self, verb: Action, object: Object, inner: bool, n: usize) -> Option<Yanked> {
+    let (from, to) = self.object(object, inner, n)?;
+    match (verb, object) {
+      // Selects the object in Visual mode
+      (Action::Move, _) => {
+        if self.anchor.is_some() {
+          self.anchor = Some(from);
+          self.cursor = to.saturating_sub(1).max(from);
+        }
+        Option::None
+      },
+      (Action::Join, _) => Option::None,
+      (_, Object::Paragraph) => {
+        let last = to.saturating_sub(1).max(from);
+        self.take_rows(verb, self.content.char_to_line(from), self.content.char_to_line(last))
+      },
+      _ => self.take(verb, from, to),
+    }
+  }
+
+  fn apply_selection(&mut self, verb: Action, visual: Visual) -> Option<Yanked> {
+    let anchor = self.anchor.take()?;
+    let (from, to) = (anchor.min(self.cursor), anchor.max(self.cursor));
+    let first = self.content.char_to_line(from);
+    let last = self.content.char_to_line(to);
+    match (verb, visual) {
+      (Action::Move, _) => Option::None,
+      (Action::Join, _) => {
+        self.cursor = from;
+        self.join((last - first).max(1));
+        Option::None
+      },
+      (_, Visual::Char) => self.take(verb, from, (to + 1).min(self.content.len_chars())),
+      (_, Visual::Line) => self.take_rows(verb, first, last),
+      (_, Visual::Block) => self.take_block(verb, anchor, first, last),
+    }
+  }
+
+  // Charwise operator on from..to, nothing for an empty stretch so the
+  // registers keep what they had
+  fn take(&mut self, verb: Action, from: usize, to: usize) -> Option<Yanked> {
+    if from == to {
+      self.cursor = from;
+      return Option::None
+    }
+    if self.transform(verb, from, to) {
+      self.cursor = from;
+      return Option::None
+    }
+    let text = self.content.slice(from..to).to_string();
+    if verb != Action::Yank {
+      self.remove(from, to);
+    }
+    self.cursor = from;
+    Some(Yanked { text, kind: YankKind::Char })
+  }
+
+  // Operator on the same columns of several rows. Rows shorter than the
+  // block give what they have.
+  fn take_block(&mut self, verb: Action, anchor: usize, first: usize, last: usize) -> Option<Yanked> {
+    let (left, right) = self.block_cols(anchor);
+    let mut pieces = Vec::new();
+    for row in (first..=last).rev() {
+      let start = self.content.line_to_char(row);
+      let len = self.row_len(row);
+      let (from, to) = (start + left.min(len), start + right.min(len));
+      pieces.push(self.content.slice(from..to).to_string());
+      match verb {
+        Action::Yank => {},
+        Action::Cut | Action::Change => self.remove(from, to),
+        _ => { self.transform(verb, from, to); },
+      }
+    }
+    pieces.reverse();
+    self.cursor = self.content.line_to_char(first) + left.min(self.row_len(first));
+    match verb {
+      Action::Cut | Action::Change | Action::Yank => {},
+      _ => return Option::None,
+    }
+    if verb == Action::Change {
+      self.block = Some(BlockInsert { first, last, col: left, start: self.cursor, append: false });
+    }
+    Some(Yanked { text: pieces.join("\n"), kind: YankKind::Block })
+  }
+
+  // Rewrites from..to for ~ u U and r, false for other verbs
+  fn transform(&mut self, verb: Action, from: usize, to: usize) -> bool {
+    let change: fn(char, char) -> String = match verb {
+      Action::SwapCase => |c, _| match c.is_uppercase() {
+        true  => c.to_lowercase().collect(),
+        false => c.to_uppercase().collect(),
+      },
+      Action::Lowercase => |c, _| c.to_lowercase().collect(),
+      Action::Uppercase => |c, _| c.to_uppercase().collect(),
+      Action::Replace(_) => |c, with| match c {
+        '\n' => c.to_string(),
+        _ => with.to_string(),
+      },
+      _ => return false,
+    };
+    let with = match verb {
+      Action::Replace(c) => c,
+      _ => ' ',
+    };
+    let text: String = self.content.slice(from..to).chars().map(|c| change(c, with)).collect();
+    self.remove(from, to);
+    self.insert_text(from, &text);
+    self.dirty = true;
+    self.retokenize();
+    true
+  }
+
+  // Linewise operator on whole rows, both ends included
+  fn take_rows(&mut self, verb: Action, from: usize, to: usize) -> Option<Yanked> {
+    let last = self.height() - 1;
+    let (from, to) = (from.min(to), from.max(to).min(last));
+    let start = self.content.line_to_char(from);
+    let end = match to == last {
+      true  => self.content.len_chars(),
+      false => self.content.line_to_char(to + 1),
+    };
+    if self.transform(verb, start, end) {
+      self.cursor = start;
+      return Option::None
+    }
+    let mut text = self.content.slice(start..end).to_string();
+    if !text.ends_with('\n') {
+      text.push('\n');
+    }
     match verb {
       Action::Cut => {
-        for _ in 0..by {
-          if dir == Dir::R {
-            self.move_cursor(1, dir)
-          }
-          self.remove_at_cursor()
+        // The last rows take the line break in front of them along
+        let start = match to == last && from > 0 {
+          true  => start - 1,
+          false => start,
+        };
+        self.remove(start, end);
+        self.goto_row(from);
+      },
+      // One empty row is left to type into
+      Action::Change => {
+        let end = match to == last {
+          true  => end,
+          false => end - 1,
+        };
+        self.remove(start, end);
+        self.cursor = start;
+      },
+      _ => if from < self.row() {
+        self.set_row(from)
+      },
+    }
+    Some(Yanked { text, kind: YankKind::Line })
+  }
+
+  // J, pulls `n` rows below up into the cursor's row
+  fn join(&mut self, n: usize) {
+    for _ in 0..n {
+      let row = self.row();
+      if row + 1 >= self.height() {
+        break
+      }
+      let end = self.end_of_row();
+      let next = self.nth(row + 1);
+      let indent = next.chars().take_while(|c| c.is_whitespace()).count();
+      let space = !next.trim().is_empty()
+        && !next.trim_start().starts_with(')')
+        && !self.nth(row).ends_with(char::is_whitespace)
+        && self.row_len(row) > 0;
+      self.remove(end, end + 1 + indent);
+      self.cursor = end;
+      if space {
+        self.insert_at_cursor(" ");
+        self.cursor = end;
+      }
+    }
+  }
+
+  // p and P, `count` copies of the register after or before the cursor
+  pub fn put(&mut self, yanked: &Yanked, before: bool, count: usize) {
+    let count = count.max(1);
+    let row = self.row();
+    match yanked.kind {
+      YankKind::Char => {
+        let text = yanked.text.repeat(count);
+        let at = match before || self.row_len(row) == 0 {
+          true  => self.cursor,
+          false => (self.cursor + 1).min(self.end_of_row()),
+        };
+        self.insert_text(at, &text);
+        // Multi-row text leaves the cursor at its start
+        self.cursor = match text.contains('\n') {
+          true  => at,
+          false => (at + text.chars().count()).saturating_sub(1).max(at),
+        };
+      },
+      YankKind::Line => {
+        let text = yanked.text.repeat(count);
+        let target = if before { row } else { row + 1 };
+        match target < self.height() {
+          true  => self.insert_text(self.content.line_to_char(target), &text),
+          // Below the last row the line break goes in front
+          false => {
+            let len = self.content.len_chars();
+            self.insert_text(len, &format!("\n{}", text.strip_suffix('\n').unwrap_or(&text)));
+          },
         }
+        self.dirty = true;
+        self.retokenize();
+        self.goto_row(target);
+        return
       },
-      Action::Move => {
-        self.move_cursor(by, dir)
+      YankKind::Block => {
+        let col = match before || self.row_len(row) == 0 {
+          true  => self.col(),
+          false => self.col() + 1,
+        };
+        let width = yanked.text.split('\n').map(|piece| piece.chars().count()).max().unwrap_or(0);
+        for (i, piece) in yanked.text.split('\n').enumerate() {
+          if row + i >= self.height() {
+            let len = self.content.len_chars();
+            self.insert_text(len, "\n");
+          }
+          let start = self.content.line_to_char(row + i);
+          let len = self.row_len(row + i);
+          // Pieces are padded to the block's width when text follows them
+          let mut text = piece.repeat(count);
+          if col < len {
+            text.push_str(&" ".repeat((width - piece.chars().count()) * count));
+          }
+          let pad = " ".repeat(col.saturating_sub(len));
+          self.insert_text(start + col.min(len), &format!("{}{}", pad, text));
+        }
+        self.cursor = self.content.line_to_char(row) + col;
       },
     }
+    self.dirty = true;
+    self.retokenize();
+    self.curswant = self.col();
+  }
+
+  // Puts `text` in place of chars from..to, for :s
+  pub fn replace(&mut self, from: usize, to: usize, text: &str) {
+    self.remove_text(from, to);
+    self.insert_text(from, text);
+    self.dirty = true;
+    self.retokenize();
+  }
+
+  // Puts new text in place of whole rows, line endings kept
+  pub fn replace_rows(&mut self, rows: &[(usize, String)]) {
+    for (row, text) in rows.iter().rev() {
+      let start = self.content.line_to_char(*row);
+      self.remove_text(start, start + self.row_len(*row));
+      self.insert_text(start, text);
+    }
+    self.dirty = true;
+    self.retokenize();
+  }
+
+  fn remove(&mut self, from: usize, to: usize) {
+    if from < to {
+      self.remove_text(from, to);
+      self.dirty = true;
+      self.retokenize();
+    }
   }
 
-  fn apply_no_dir(&mut self, verb: Action, dest: Dest) {
-    // match verb {
-    //   Action::Cut => match dest {
-    //     // Maybe should make this actually work, but this is how it works in vim.
-    //     Dest::TxtStart => self.delete_lines_down(1),
-    //     Dest::Line     => self.delete_lines_down(1),
-    //     Dest::Endl     => self.remove_at_cursor(usize::MAX, Dir::R),
-    //   },
-    //   Action::Move => match dest {
-    //     Dest::Endl => {
-    //       self.byte = self.nth(self.row).len();
-    //       self.char = self.char_len();
-    //     },
-    //     Dest::TxtStart => {
-    //       self.byte = self.leading_whitespaces(self.row);
-    //       self.char = self.byte;
-    //     }
-    //     _ => panic!("Invalid dest for Action::Move\n{:?}", dest)
-    //   }
-    // }
-  }
-
-  fn remove_at_cursor(&mut self) {
-    let byte = self.byte_index();
-    if !self.content.is_empty() {
-      self.content.remove(byte);
-    }
-    self.cursor = self.cursor.saturating_sub(1);
-  }
-  
   fn move_cursor(&mut self, by: usize, dir: Dir) {
     self.cursor = match dir {
-      Dir::L => self.cursor.saturating_sub(by),
-      Dir::R => {
-        let r_bound = self.end_of_row();
-        if self.cursor + by <= r_bound {
-          self.cursor + by
-        } else {
-          r_bound
+      Dir::L => self.cursor.saturating_sub(by).max(self.start_of_row()),
+      Dir::R => (self.cursor + by).min(self.end_of_row()),
+      // A closed fold counts as one row
+      Dir::U | Dir::D => {
+        let mut row = self.row();
+        for _ in 0..by {
+          row = match dir {
+            Dir::U => row.saturating_sub(1),
+            _      => (self.fold_at(row).map_or(row, |fold| fold.1) + 1).min(self.height() - 1),
+          };
+          row = self.fold_at(row).map_or(row, |fold| fold.0);
         }
+        self.content.line_to_char(row) + self.curswant.min(self.row_len(row))
       },
-      _      => todo!("Move up and down")
     }
   }
 
-  // absolute index of next end of line from cursor pov
+  // Cursor to the first non-blank of `row`
+  pub fn goto_row(&mut self, row: usize) {
+    let row = row.min(self.height() - 1);
+    let indent = self.nth(row).chars().take_while(|c| c.is_whitespace()).count();
+    self.cursor = self.content.line_to_char(row) + indent.min(self.row_len(row).saturating_sub(1));
+    self.curswant = self.col();
+  }
+
+  // absolute index of the start of the cursor's row
+  fn start_of_row(&self) -> usize {
+    self.content.line_to_char(self.row())
+  }
+
+  // absolute index just past the last char of the cursor's row
   fn end_of_row(&self) -> usize {
-    self.content
-      .char_indices()
-      .skip(self.cursor)
-      .take_while(|(_, c)| *c != '\n')
-      .last()
-      .unwrap_or((self.cursor, ' '))
-      .0
+    self.start_of_row() + self.row_len(self.row())
   }
 
+  // chars in row n, line ending excluded
+  pub fn row_len(&self, n: usize) -> usize {
+    self.nth(n).chars().count()
+  }
+
+  // Edit mode cursor sits on a char, never past the end of a row
   fn jump_back_if_end(&mut self) {
-    if self.content.chars().nth(self.cursor+1) == Some('\n') {
-      self.cursor = match self.content.char_indices().nth(self.cursor.saturating_sub(1)) {
-        Some(c) => c.0,
-        None => self.cursor
-      };
-      println!("Jumped back at end");
+    if self.cursor == self.end_of_row() && self.cursor > self.start_of_row() {
+      self.cursor -= 1;
     }
   }
+
   pub fn height(&self) -> usize {
-    self.content.lines().count()
-  } 
-
-  pub fn seek(&mut self, target: Seek, forwards: bool) {
-    self.cursor = match target {
-      Seek::Char(c) => match forwards {
-        true  => self.seek_next_char(c),
-        false => self.seek_prev_char(c)
-      },
-      Seek::Word => match forwards {
-        true  => self.seek_next_word(),
-        false => self.seek_prev_word()
-      },
-    }
+    self.content.len_lines()
   }
 
   pub fn insert_at_cursor(&mut self, s: &str) {
-    println!("Byte index: {}, cursor: {}", self.byte_index(), self.cursor);
-    let mut byte = self.content
-      .char_indices()
-      .take(self.cursor)
-      .last()
-      .map(|(idx, c)| idx + c.len_utf8().saturating_sub(1))
-      .unwrap_or(0);
+    self.insert_text(self.cursor, s);
+    self.cursor += s.chars().count();
+    self.curswant = self.col();
+    self.dirty = true;
+    self.retokenize();
+  }
 
-    if !self.content.is_empty() {
-      byte += 1;
+  // Applies undo steps as edits, leaving the cursor where the last one
+  // starts. The buffer is dirty unless the steps led back to the text
+  // last written.
+  fn step(&mut self, steps: Option<Vec<Delta>>) {
+    let Some(steps) = steps else { return };
+    for step in &steps {
+      let to = step.from + step.removed.chars().count();
+      if step.from < to {
+        self.remove_text(step.from, to);
+      }
+      if !step.inserted.is_empty() {
+        self.insert_text(step.from, &step.inserted);
+      }
+      self.cursor = step.from;
     }
-
-    self.content.insert_str(byte, s);
-    self.cursor += s.chars().count();
+    self.uncommitted = None;
+    self.committed = self.content.clone();
+    self.dirty = !self.history.is_saved();
     self.retokenize();
+    self.set_cursor(self.cursor.min(self.content.len_chars().saturating_sub(1)));
   }
 
   pub fn set_cursor(&mut self, n: usize) {
-    self.cursor = n
+    self.cursor = n.min(self.content.len_chars());
+    self.curswant = self.col();
   }
 
-  fn seek_next_char(&self, c: char) -> usize {
-    self.content
-      .chars()
-      .enumerate()
-      .skip(self.cursor+1)
-      .find(|a| a.1 == c)
-      .map(|f| f.0)
-      .unwrap_or(self.cursor)
+  // Screen lines taken by row n when wrapped at `cols`. A closed fold
+  // shows as one line.
+  pub fn display_height(&self, n: usize, cols: usize) -> usize {
+    match self.fold_at(n) {
+      Some((first, _)) if n > first => 0,
+      Some(_) => 1,
+      None => self.row_len(n).max(1).div_ceil(cols.max(1)),
+    }
   }
 
-  fn seek_prev_char(&self, c: char) -> usize{
-    self.content
-      .chars()
-      .enumerate()
-      .take_while(|f| f.0 < self.cursor)
-      .filter(|f| f.1 == c)
-      .map(|f| f.0)
-      .last()
-      .unwrap_or(self.cursor)
+  // gj / gk, moves by screen lines of rows wrapped at `cols`
+  pub fn move_display_line(&mut self, n: isize, cols: usize) {
+    let cols = cols.max(1);
+    for _ in 0..n.unsigned_abs() {
+      let (row, col) = (self.row(), self.col());
+      let len = self.row_len(row);
+      let (sub, offset) = (col / cols, self.curswant % cols);
+      let (row, col) = match n > 0 {
+        true if (sub + 1) * cols < len => (row, col + cols),
+        true if row + 1 < self.height() => (row + 1, offset),
+        false if sub > 0 => (row, col - cols),
+        false if row > 0 => {
+          let last_sub = self.row_len(row - 1).saturating_sub(1) / cols;
+          (row - 1, last_sub * cols + offset)
+        },
+        _ => return,
+      };
+      let col = col.min(self.row_len(row).saturating_sub(1));
+      self.cursor = self.content.line_to_char(row) + col;
+    }
   }
 
-  fn seek_next_word(&self) -> usize{
-    match self.token_list
-      .iter()
-      .filter(|token| token.kind() == TokenKind::Word)
-      .find(|token| token.position().0 > self.cursor) {
-        Some(token) => token.position().0,
-        None        => self.cursor
+  // Moves the cursor to `row`, keeping the column where the row allows
+  pub fn set_row(&mut self, row: usize) {
+    let row = row.min(self.height() - 1);
+    let col = self.curswant.min(self.row_len(row).saturating_sub(1));
+    self.cursor = self.content.line_to_char(row) + col;
+  }
+
+  fn seek_from(&self, from: usize, target: Seek, forwards: bool) -> usize {
+    match (target, forwards) {
+      (Seek::Char(_) | Seek::Till(..), _) => self.find_in_row(from, target, forwards, 1).unwrap_or(from),
+      (Seek::Word, true)  => self.seek_next_word(from, false),
+      (Seek::Word, false) => self.seek_prev_word(from, false),
+      (Seek::BigWord, true)  => self.seek_next_word(from, true),
+      (Seek::BigWord, false) => self.seek_prev_word(from, true),
+      (Seek::WordEnd, true)  => self.seek_next_word_end(from, false),
+      (Seek::WordEnd, false) => self.seek_prev_word_end(from, false),
+      (Seek::BigWordEnd, true)  => self.seek_next_word_end(from, true),
+      (Seek::BigWordEnd, false) => self.seek_prev_word_end(from, true),
+      (Seek::Sentence, true) => self.sentences()
+        .into_iter()
+        .find(|pos| *pos > from)
+        .unwrap_or(self.content.len_chars()),
+      (Seek::Sentence, false) => self.sentences()
+        .into_iter()
+        .rfind(|pos| *pos < from)
+        .unwrap_or(0),
+      (Seek::Paragraph, true) => ((self.content.char_to_line(from) + 1)..self.height())
+        .find(|row| self.row_len(*row) == 0)
+        .map_or(self.content.len_chars(), |row| self.content.line_to_char(row)),
+      (Seek::Paragraph, false) => (0..self.content.char_to_line(from))
+        .rfind(|row| self.row_len(*row) == 0)
+        .map_or(0, |row| self.content.line_to_char(row)),
+    }
+  }
+
+  // f, F, t and T look for the `n`th char on the row of `from`
+  fn find_in_row(&self, from: usize, seek: Seek, forwards: bool, n: usize) -> Option<usize> {
+    let (c, till, skip) = match seek {
+      Seek::Char(c) => (c, false, false),
+      Seek::Till(c, skip) => (c, true, skip),
+      _ => return None,
+    };
+    let row = self.content.char_to_line(from);
+    let start = self.content.line_to_char(row);
+    let end = start + self.row_len(row);
+    let adjacent = match forwards {
+      true  => from + 1 < end && self.content.char(from + 1) == c,
+      false => from > start && self.content.char(from - 1) == c,
+    };
+    // A repeated t would stay in place in front of the same char
+    let n = match till && skip && n == 1 && adjacent {
+      true  => 2,
+      false => n,
+    };
+    let found = match forwards {
+      true  => (from + 1..end).filter(|i| self.content.char(*i) == c).nth(n - 1)?,
+      false => (start..from).rev().filter(|i| self.content.char(*i) == c).nth(n - 1)?,
+    };
+    match (till, forwards) {
+      (false, _) => Some(found),
+      (true, true) => Some(found - 1),
+      (true, false) => Some(found + 1),
+    }
+  }
+
+  fn seek_next_word(&self, from: usize, big: bool) -> usize {
+    match self.words(big).into_iter().find(|word| word.0 > from) {
+      Some(word) => word.0,
+      None       => self.content.len_chars(),
+    }
+  }
+
+  fn seek_prev_word(&self, from: usize, big: bool) -> usize {
+    match self.words(big).into_iter().rfind(|word| word.0 < from) {
+      Some(word) => word.0,
+      None       => 0,
+    }
+  }
+
+  // e skips empty rows, ge stops on them
+  fn seek_next_word_end(&self, from: usize, big: bool) -> usize {
+    self.words(big)
+      .into_iter()
+      .find(|word| word.1 > from && !self.is_empty_row_at(word.0))
+      .map_or(self.content.len_chars().saturating_sub(1), |word| word.1)
+  }
+
+  fn seek_prev_word_end(&self, from: usize, big: bool) -> usize {
+    self.words(big)
+      .into_iter()
+      .rfind(|word| word.1 < from)
+      .map_or(0, |word| word.1)
+  }
+
+  fn is_empty_row_at(&self, idx: usize) -> bool {
+    self.row_len(self.content.char_to_line(idx)) == 0
+  }
+
+  // Word spans (first, last char) built from the token list. Keyword
+  // tokens merge with their keyword neighbours and punctuation with
+  // punctuation, with `big` every run of non-blanks is one WORD.
+  // Empty rows count as words of their own.
+  fn words(&self, big: bool) -> Vec<(usize, usize)> {
+    let class = |kind: TokenKind| match kind {
+      TokenKind::Whitespace | TokenKind::NewLine => 0,
+      _ if big => 1,
+      TokenKind::Word | TokenKind::Number => 1,
+      _ => 2,
+    };
+    let mut words: Vec<(usize, usize)> = Vec::new();
+    let mut prev = 0;
+    for token in &self.token_list {
+      let (first, last) = token.position();
+      let kind = class(token.kind());
+      if kind == 0 {
+        if token.kind() == TokenKind::NewLine {
+          for pos in first..=last {
+            if pos == 0 || self.content.char(pos - 1) == '\n' {
+              words.push((pos, pos))
+            }
+          }
+        }
+      } else if kind == prev {
+        if let Some(word) = words.last_mut() {
+          word.1 = last
+        }
+      } else {
+        words.push((first, last))
       }
+      prev = kind;
+    }
+    words
   }
 
-  fn seek_prev_word(&self) -> usize {
-    todo!()
+  // Sentences start after '.', '!' or '?' (and any closing brackets or
+  // quotes) followed by white space, and at paragraph boundaries.
+  fn sentences(&self) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut boundary = true;
+    let mut ended = false;
+    let mut prev = '\n';
+    for (idx, c) in self.content.chars().enumerate() {
+      if c == '\n' && prev == '\n' {
+        starts.push(idx);
+        boundary = true;
+      } else if c.is_whitespace() {
+        if ended {
+          boundary = true;
+        }
+        ended = false;
+      } else {
+        if boundary {
+          starts.push(idx);
+          boundary = false;
+        }
+        ended = match c {
+          '.' | '!' | '?' => true,
+          ')' | ']' | '"' | '\'' => ended,
+          _ => false,
+        };
+      }
+      prev = c;
+    }
+    starts
   }
 
-  fn retokenize(&mut self) {
-    self.token_list = Lexer::from(self.content.as_str()).collect()
+  //------------------------------------------------------
+  // Text objects
+  //------------------------------------------------------
+  // Range from..to covered by the `n`th text object around the cursor
+  pub fn object(&self, object: Object, inner: bool, n: usize) -> Option<(usize, usize)> {
+    let n = n.max(1);
+    match object {
+      Object::Word => self.word_object(false, inner, n),
+      Object::BigWord => self.word_object(true, inner, n),
+      Object::Sentence => self.sentence_object(inner, n),
+      Object::Paragraph => self.paragraph_object(inner, n),
+      Object::Quote(quote) => self.quote_object(quote, inner),
+      Object::Bracket(open, close) => self.bracket_object(open, close, inner, n),
+      Object::Tag => self.tag_object(inner, n),
+      #[cfg(feature = "tree-sitter")]
+      Object::Function | Object::Class | Object::Argument | Object::Block => {
+        self.tree.as_ref()?.object(&self.content, object, inner, self.cursor, n)
+      },
+      #[cfg(not(feature = "tree-sitter"))]
+      Object::Function | Object::Class | Object::Argument | Object::Block => None,
+    }
+  }
+
+  // iw counts words and the blanks between them, aw takes a word with
+  // the blanks after it, or before it when there are none after.
+  fn word_object(&self, big: bool, inner: bool, n: usize) -> Option<(usize, usize)> {
+    let start = self.start_of_row();
+    let end = start + self.row_len(self.row());
+    // (from, to, blank) for every word and gap on the row
+    let mut spans = Vec::new();
+    let mut pos = start;
+    for word in self.words(big).into_iter().filter(|word| word.0 >= start && word.1 < end) {
+      if word.0 > pos {
+        spans.push((pos, word.0, true));
+      }
+      spans.push((word.0, word.1 + 1, false));
+      pos = word.1 + 1;
+    }
+    if pos < end {
+      spans.push((pos, end, true));
+    }
+    let first = spans.iter().position(|span| span.0 <= self.cursor && self.cursor < span.1)?;
+    let last = match inner {
+      true  => first + n - 1,
+      false => first + 2 * n - 1,
+    }.min(spans.len() - 1);
+    let mut from = spans[first].0;
+    if !inner && !spans[first].2 && !spans[last].2 && first > 0 && spans[first - 1].2 {
+      from = spans[first - 1].0;
+    }
+    Some((from, spans[last].1))
+  }
+
+  fn sentence_object(&self, inner: bool, n: usize) -> Option<(usize, usize)> {
+    let starts = self.sentences();
+    let first = starts.iter().rposition(|pos| *pos <= self.cursor)?;
+    let from = starts[first];
+    let end = starts.get(first + n).copied().unwrap_or(self.content.len_chars());
+    let mut to = end;
+    while to > from && self.content.char(to - 1).is_whitespace() {
+      to -= 1;
+    }
+    if inner {
+      return Some((from, to))
+    }
+    // Without white space after the sentence, take the one before it
+    let mut from = from;
+    if to == end {
+      while from > 0 && matches!(self.content.char(from - 1), ' ' | '\t') {
+        from -= 1;
+      }
+    }
+    Some((from, end))
+  }
+
+  // Linewise. ip counts paragraphs and the blank rows between them, ap
+  // takes a paragraph with the blank rows after it.
+  fn paragraph_object(&self, inner: bool, n: usize) -> Option<(usize, usize)> {
+    let blank = |row: usize| self.nth(row).trim().is_empty();
+    let last = self.height() - 1;
+    let row = self.row();
+    let mut first = row;
+    while first > 0 && blank(first - 1) == blank(row) {
+      first -= 1;
+    }
+    let runs = match inner {
+      true  => n,
+      false => 2 * n,
+    };
+    let mut end = row;
+    for run in 0..runs {
+      if run > 0 {
+        if end == last {
+          break
+        }
+        end += 1;
+      }
+      while end < last && blank(end + 1) == blank(end) {
+        end += 1;
+      }
+    }
+    // No blank rows after the paragraph, take the ones before it
+    if !inner && !blank(row) && !blank(end) {
+      while first > 0 && blank(first - 1) {
+        first -= 1;
+      }
+    }
+    let to = match end == last {
+      true  => self.content.len_chars(),
+      false => self.content.line_to_char(end + 1),
+    };
+    Some((self.content.line_to_char(first), to))
   }
 
-  fn byte_index(&self) -> usize {
-    if let Some(idx) = self.content
-      .char_indices()
-      .nth(self.cursor.saturating_sub(1)) 
+  // Quotes pair up from the start of the row, the pair around the cursor
+  // or else the first one after it is used.
+  fn quote_object(&self, quote: char, inner: bool) -> Option<(usize, usize)> {
+    let start = self.start_of_row();
+    let line: Vec<char> = self.nth(self.row()).chars().collect();
+    let quotes: Vec<usize> = (0..line.len())
+      .filter(|i| line[*i] == quote && (*i == 0 || line[i - 1] != '\\'))
+      .collect();
+    let col = self.col();
+    let (open, close) = quotes
+      .chunks_exact(2)
+      .map(|pair| (pair[0], pair[1]))
+      .find(|pair| col <= pair.1)?;
+    if inner {
+      return Some((start + open + 1, start + close))
+    }
+    let trailing = line[close + 1..].iter().take_while(|c| c.is_whitespace()).count();
+    let leading = match trailing {
+      0 => line[..open].iter().rev().take_while(|c| c.is_whitespace()).count(),
+      _ => 0,
+    };
+    Some((start + open - leading, start + close + 1 + trailing))
+  }
+
+  // The `n`th pair of brackets around the cursor. Inner objects spanning
+  // several rows leave the rows with the brackets alone.
+  fn bracket_object(&self, open: char, close: char, inner: bool, n: usize) -> Option<(usize, usize)> {
+    let len = self.content.len_chars();
+    let mut chars = self.content.chars_at((self.cursor + 1).min(len));
+    let mut idx = (self.cursor + 1).min(len);
+    let mut depth = 0;
+    let mut left = n;
+    let from = loop {
+      let c = chars.prev()?;
+      idx -= 1;
+      if c == close && idx != self.cursor {
+        depth += 1;
+      } else if c == open && depth > 0 {
+        depth -= 1;
+      } else if c == open {
+        left -= 1;
+        if left == 0 {
+          break idx
+        }
+      }
+    };
+    let mut depth = 0;
+    let to = self.content
+      .chars_at(from + 1)
+      .enumerate()
+      .find(|(_, c)| {
+        match *c {
+          c if c == close && depth == 0 => return true,
+          c if c == close => depth -= 1,
+          c if c == open => depth += 1,
+          _ => {},
+        }
+        false
+      })
+      .map(|(i, _)| from + 1 + i)?;
+    if !inner {
+      return Some((from, to + 1))
+    }
+    let mut inner_from = from + 1;
+    let mut inner_to = to;
+    let close_row = self.content.char_to_line(to);
+    let row_start = self.content.line_to_char(close_row);
+    if self.content.get_char(inner_from) == Some('\n') && close_row > self.content.char_to_line(from) {
+      inner_from += 1;
+      if self.content.slice(row_start..to).chars().all(char::is_whitespace) {
+        inner_to = row_start;
+      }
+    }
+    Some((inner_from, inner_to.max(inner_from)))
+  }
+
+  // Tags are paired up over the whole buffer, `n` counts outwards from
+  // the innermost pair around the cursor.
+  fn tag_object(&self, inner: bool, n: usize) -> Option<(usize, usize)> {
+    let text: Vec<char> = self.content.chars().collect();
+    // Name, start and end of each open tag
+    let mut stack: Vec<(String, usize, usize)> = Vec::new();
+    let mut pairs = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+      if text[i] != '<' {
+        i += 1;
+        continue
+      }
+      let Some(end) = text[i..].iter().position(|c| *c == '>').map(|e| i + e + 1) else { break };
+      let tag: String = text[i + 1..end - 1].iter().collect();
+      if let Some(name) = tag.strip_prefix('/') {
+        if let Some(k) = stack.iter().rposition(|open| open.0 == name.trim()) {
+          pairs.push((stack[k].1, stack[k].2, i, end));
+          stack.truncate(k);
+        }
+      } else if !tag.ends_with('/') {
+        let name = tag.split_whitespace().next().unwrap_or("");
+        if name.starts_with(char::is_alphabetic) {
+          stack.push((name.to_string(), i, end));
+        }
+      }
+      i = end;
+    }
+    pairs.retain(|pair| pair.0 <= self.cursor && self.cursor < pair.3);
+    pairs.sort_by_key(|pair| pair.3 - pair.0);
+    let pair = pairs.get(n - 1)?;
+    match inner {
+      true  => Some((pair.1, pair.2)),
+      false => Some((pair.0, pair.3)),
+    }
+  }
+
+  //------------------------------------------------------
+  // Syntax
+  //------------------------------------------------------
+  pub fn language(&self) -> Language {
+    self.syntax.language()
+  }
+
+  pub fn set_language(&mut self, language: Language) {
+    self.syntax.set_language(language, &self.content);
+    #[cfg(feature = "tree-sitter")]
     {
-      idx.0
+      self.tree = SyntaxTree::new(language, &self.content);
+    }
+  }
+
+  // Highlighted spans of row n, none for plain text. The syntax tree
+  // takes over from the lexers where there is one.
+  pub fn spans(&self, n: usize) -> Vec<Span> {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(tree) = &self.tree {
+      return tree.spans(&self.content, n)
+    }
+    self.syntax.spans(n).to_vec()
+  }
+
+  // Where % goes: the other end of the syntax node or bracket pair the
+  // cursor is on
+  fn partner(&self) -> Option<usize> {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(to) = self.tree.as_ref().and_then(|tree| tree.partner(&self.content, self.cursor)) {
+      return Some(to)
+    }
+    self.bracket_partner()
+  }
+
+  // Without a syntax tree % looks for a bracket from the cursor on along
+  // the row and goes to the one pairing with it
+  fn bracket_partner(&self) -> Option<usize> {
+    let (at, c) = (self.cursor..self.end_of_row())
+      .map(|i| (i, self.content.char(i)))
+      .find(|(_, c)| "(){}[]".contains(*c))?;
+    let (open, close) = match c {
+      '(' | ')' => ('(', ')'),
+      '{' | '}' => ('{', '}'),
+      _ => ('[', ']'),
+    };
+    let mut depth = 0;
+    if c == open {
+      for (i, c) in self.content.chars_at(at).enumerate() {
+        if c == open {
+          depth += 1;
+        } else if c == close {
+          depth -= 1;
+          if depth == 0 {
+            return Some(at + i)
+          }
+        }
+      }
     } else {
-      0
+      let mut chars = self.content.chars_at(at + 1);
+      let mut i = at + 1;
+      while let Some(c) = chars.prev() {
+        i -= 1;
+        if c == close {
+          depth += 1;
+        } else if c == open {
+          depth -= 1;
+          if depth == 0 {
+            return Some(i)
+          }
+        }
+      }
     }
+    None
   }
 
-  pub fn col(&self) -> usize {
-    let mut column = 0;
-    for c in self.content.chars().enumerate() {
-      if c.1 != '\n' {
-        column += 1
-      } else {
-        column = 0
+  //------------------------------------------------------
+  // Folds
+  //------------------------------------------------------
+  // First and last row of the closed fold holding row n
+  pub fn fold_at(&self, n: usize) -> Option<(usize, usize)> {
+    self.folds
+      .iter()
+      .map(|(first, last)| (self.content.char_to_line(*first), self.content.char_to_line(*last)))
+      .find(|(first, last)| *first <= n && n <= *last)
+  }
+
+  // zc closes the innermost fold around the cursor, or the one around
+  // the closed fold it is on, zo and za open and toggle it, zR opens
+  // every fold and zM closes the outermost ones
+  pub fn fold(&mut self, key: char) -> Result<(), String> {
+    let row = self.row();
+    let closed = self.fold_at(row);
+    match key {
+      'o' => {
+        let (first, _) = closed.ok_or("No fold found")?;
+        self.folds.retain(|fold| self.content.char_to_line(fold.0) != first);
+      },
+      'c' => {
+        let fold = self
+          .fold_ranges(row)
+          .into_iter()
+          .find(|fold| closed.is_none_or(|(first, last)| fold.0 <= first && last <= fold.1 && *fold != (first, last)))
+          .ok_or("No fold found")?;
+        self.close_fold(fold);
+      },
+      'a' => return self.fold(if closed.is_some() { 'o' } else { 'c' }),
+      'R' => self.folds.clear(),
+      'M' => for fold in self.top_folds() {
+        self.close_fold(fold)
+      },
+      _ => {},
+    }
+    Ok(())
+  }
+
+  // Opens the fold the cursor was moved into, unless it is on the row
+  // the fold shows as
+  pub fn reveal_cursor(&mut self) {
+    let row = self.row();
+    if let Some((first, _)) = self.fold_at(row).filter(|(first, _)| *first < row) {
+      self.folds.retain(|fold| self.content.char_to_line(fold.0) != first);
+    }
+  }
+
+  fn close_fold(&mut self, (first, last): (usize, usize)) {
+    let content = &self.content;
+    self.folds.retain(|fold| content.char_to_line(fold.1) < first || content.char_to_line(fold.0) > last);
+    self.folds.push((content.line_to_char(first), content.line_to_char(last)));
+    self.folds.sort();
+    if self.row() > first {
+      self.set_row(first);
+    }
+  }
+
+  // Foldable rows around row n, innermost first. They come from the
+  // syntax tree, so there are none without it.
+  fn fold_ranges(&self, n: usize) -> Vec<(usize, usize)> {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(tree) = &self.tree {
+      return tree.folds(&self.content, n)
+    }
+    let _ = n;
+    Vec::new()
+  }
+
+  fn top_folds(&self) -> Vec<(usize, usize)> {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(tree) = &self.tree {
+      return tree.top_folds()
+    }
+    Vec::new()
+  }
+
+  //------------------------------------------------------
+  // Search
+  //------------------------------------------------------
+  // Start of the first match after `from`, or the last one before it
+  // searching backwards, and whether the search wrapped around
+  pub fn search(&self, pattern: &Pattern, from: usize, forward: bool) -> Option<(usize, bool)> {
+    match forward {
+      true  => self.find_forward(pattern, from + 1).map(|at| (at, false))
+        .or_else(|| self.find_forward(pattern, 0).map(|at| (at, true))),
+      false => self.find_backward(pattern, from).map(|at| (at, false))
+        .or_else(|| self.find_backward(pattern, self.content.len_chars()).map(|at| (at, true))),
+    }
+  }
+
+  // First match starting at or after `from`. Rows are searched a window
+  // at a time and one grows while a match runs into its end. Windows
+  // overlap by a row, unless the pattern can span lines: then the window
+  // keeps its start and grows, as a match may start anywhere in it.
+  fn find_forward(&self, pattern: &Pattern, from: usize) -> Option<usize> {
+    let len = self.content.len_chars();
+    let mut from = from.min(len);
+    let mut rows = SEARCH_ROWS;
+    loop {
+      let row = self.content.char_to_line(from);
+      let start = self.content.line_to_char(row);
+      let end = self.content.line_to_char((row + rows).min(self.height()));
+      let text = self.content.slice(start..end).to_string();
+      let base = self.content.char_to_byte(start);
+      let offset = self.content.char_to_byte(from) - base;
+      match pattern.regex().find_at(&text, offset) {
+        Some(m) if m.end() < text.len() || end == len => return Some(self.content.byte_to_char(base + m.start())),
+        Some(_) => rows *= 2,
+        None if end == len => return None,
+        None if pattern.spans_lines() => rows *= 2,
+        None => from = from.max(self.content.line_to_char(row + rows - 1)),
       }
-      if c.0 == self.cursor {
-        break
+    }
+  }
+
+  // Last match starting before `until`, searched for in windows of rows
+  // going back from the row after it. A pattern that can span lines is
+  // matched up to the end of the buffer, the match may end anywhere.
+  fn find_backward(&self, pattern: &Pattern, until: usize) -> Option<usize> {
+    let row = self.content.char_to_line(until);
+    let mut end = match pattern.spans_lines() {
+      true  => self.content.len_chars(),
+      false => self.content.line_to_char((row + 2).min(self.height())),
+    };
+    let mut first = row.saturating_sub(SEARCH_ROWS);
+    loop {
+      let start = self.content.line_to_char(first);
+      let text = self.content.slice(start..end).to_string();
+      let base = self.content.char_to_byte(start);
+      let last = pattern
+        .regex()
+        .find_iter(&text)
+        .map(|m| self.content.byte_to_char(base + m.start()))
+        .take_while(|at| *at < until)
+        .last();
+      if last.is_some() || first == 0 {
+        return last
+      }
+      if !pattern.spans_lines() {
+        end = self.content.line_to_char(first + 1);
+      }
+      first = first.saturating_sub(SEARCH_ROWS);
+    }
+  }
+
+  // Columns from..to of the matches on `row`
+  pub fn matches(&self, row: usize, pattern: &Pattern) -> Vec<(usize, usize)> {
+    let line = self.nth(row);
+    let col = |byte: usize| line[..byte].chars().count();
+    pattern
+      .find_all(&line)
+      .into_iter()
+      .map(|m| (col(m.start), col(m.end)))
+      .collect()
+  }
+
+  // Start and text of the word under or after the cursor on its row,
+  // for * and #
+  pub fn word_under_cursor(&self) -> Option<(usize, String)> {
+    let end = self.end_of_row();
+    self.words(false)
+      .into_iter()
+      .filter(|(_, last)| *last >= self.cursor && *last < end)
+      .find(|(first, _)| matches!(get_kind(self.content.char(*first)), TokenKind::Word | TokenKind::Number))
+      .map(|(first, last)| (first, self.content.slice(first..=last).to_string()))
+  }
+
+  //------------------------------------------------------
+  // Visual mode
+  //------------------------------------------------------
+  pub fn start_visual(&mut self) {
+    self.anchor.get_or_insert(self.cursor);
+  }
+
+  pub fn end_visual(&mut self) {
+    if let Some(anchor) = self.anchor.take() {
+      let rows = (self.content.char_to_line(anchor), self.row());
+      self.last_visual = Some((rows.0.min(rows.1), rows.0.max(rows.1)));
+      self.jump_back_if_end();
+    }
+  }
+
+  pub fn last_visual(&self) -> Option<(usize, usize)> {
+    self.last_visual
+  }
+
+  // Swaps the cursor to the other end of the selection, v_o
+  pub fn other_end(&mut self) {
+    if let Some(anchor) = self.anchor.replace(self.cursor) {
+      self.set_cursor(anchor);
+    }
+  }
+
+  // Columns from..to of `row` inside the selection. Row ends taken by
+  // a charwise or linewise selection get one column so they show.
+  pub fn selected_cols(&self, row: usize, visual: Visual) -> Option<(usize, usize)> {
+    let anchor = self.anchor?;
+    let (from, to) = (anchor.min(self.cursor), anchor.max(self.cursor));
+    let first = self.content.char_to_line(from);
+    let last = self.content.char_to_line(to);
+    if row < first || row > last {
+      return Option::None
+    }
+    let start = self.content.line_to_char(row);
+    let len = self.row_len(row);
+    match visual {
+      Visual::Char => Some((
+        if row == first { from - start } else { 0 },
+        if row == last { to - start + 1 } else { len + 1 },
+      )),
+      Visual::Line => Some((0, len + 1)),
+      Visual::Block => {
+        let (left, right) = self.block_cols(anchor);
+        Some((left.min(len), right.min(len)))
+      },
+    }
+  }
+
+  // Size of the selection for repeating its operator with `.`: rows
+  // below the first and, charwise, the chars after the start on a single
+  // row or the end column, blockwise the columns after the left one
+  pub fn selection_extent(&self, visual: Visual) -> Option<(usize, usize)> {
+    let anchor = self.anchor?;
+    let (from, to) = (anchor.min(self.cursor), anchor.max(self.cursor));
+    let rows = self.content.char_to_line(to) - self.content.char_to_line(from);
+    let cols = match visual {
+      Visual::Char if rows == 0 => to - from,
+      Visual::Char => to - self.content.line_to_char(self.content.char_to_line(to)),
+      Visual::Line => 0,
+      Visual::Block => match self.block_cols(anchor) {
+        (_, usize::MAX) => usize::MAX,
+        (left, right) => right - left - 1,
+      },
+    };
+    Some((rows, cols))
+  }
+
+  // Selects an extent taken with selection_extent from the cursor
+  pub fn select_extent(&mut self, visual: Visual, (rows, cols): (usize, usize)) {
+    self.anchor = Some(self.cursor);
+    let row = (self.row() + rows).min(self.height() - 1);
+    let col = match visual {
+      Visual::Char if rows == 0 => self.col() + cols,
+      Visual::Char => cols,
+      Visual::Line => 0,
+      Visual::Block => self.col().saturating_add(cols),
+    };
+    let col = col.min(self.row_len(row).saturating_sub(1));
+    self.cursor = self.content.line_to_char(row) + col;
+    self.curswant = match cols {
+      usize::MAX => usize::MAX,
+      _ => col,
+    };
+  }
+
+  // Columns left..right of a blockwise selection, right is usize::MAX
+  // after $
+  fn block_cols(&self, anchor: usize) -> (usize, usize) {
+    let anchor_col = anchor - self.content.line_to_char(self.content.char_to_line(anchor));
+    let col = self.col();
+    let right = match self.curswant {
+      usize::MAX => usize::MAX,
+      _ => anchor_col.max(col) + 1,
+    };
+    (anchor_col.min(col), right)
+  }
+
+  // > and < on the selected rows, `n` shifts of `width` columns each
+  pub fn shift_selection(&mut self, n: isize, width: usize) {
+    let anchor = self.anchor.take().unwrap_or(self.cursor);
+    let first = self.content.char_to_line(anchor.min(self.cursor));
+    let last = self.content.char_to_line(anchor.max(self.cursor));
+    let by = n.unsigned_abs() * width;
+    for row in first..=last {
+      let start = self.content.line_to_char(row);
+      match n > 0 {
+        true if self.row_len(row) > 0 => self.insert_text(start, &" ".repeat(by)),
+        true => {},
+        false => {
+          let mut cols = 0;
+          let indent = self.nth(row)
+            .chars()
+            .take_while(|c| {
+              let fits = cols < by && (*c == ' ' || *c == '\t');
+              cols += if *c == '\t' { width } else { 1 };
+              fits
+            })
+            .count();
+          self.remove_text(start, start + indent);
+        },
       }
     }
-    column
+    self.dirty = true;
+    self.retokenize();
+    self.goto_row(first);
   }
 
-  pub fn line_count(&self) -> usize {
-    self.content.chars().filter(|c| *c == '\n').count()
+  // Visual-block I and A. Insert mode starts on the first row, the rest
+  // get the same text when it ends.
+  pub fn block_insert(&mut self, append: bool) {
+    let Some(anchor) = self.anchor.take() else { return };
+    let (left, right) = self.block_cols(anchor);
+    let first = self.content.char_to_line(anchor.min(self.cursor));
+    let last = self.content.char_to_line(anchor.max(self.cursor));
+    let col = match append {
+      true  => right,
+      false => left,
+    };
+    let start = self.content.line_to_char(first);
+    let len = self.row_len(first);
+    self.cursor = start + col.min(len);
+    if append && col != usize::MAX && col > len {
+      self.insert_at_cursor(&" ".repeat(col - len));
+    }
+    self.block = Some(BlockInsert { first, last, col, start: self.cursor, append });
   }
 
-  pub fn nth(&self, n: usize) -> &str {
-    self.content.lines().nth(n).unwrap_or("")
+  // Repeats the text typed on the first row of a block insert on the
+  // others. Returns false when no block insert was going on.
+  pub fn finish_block_insert(&mut self) -> bool {
+    let Some(block) = self.block.take() else { return false };
+    let typed = match self.cursor > block.start && self.row() == block.first {
+      true  => self.content.slice(block.start..self.cursor).to_string(),
+      false => String::new(),
+    };
+    if !typed.is_empty() && !typed.contains('\n') {
+      for row in block.first + 1..=block.last.min(self.height() - 1) {
+        let start = self.content.line_to_char(row);
+        let len = self.row_len(row);
+        let (col, pad) = match block.col {
+          usize::MAX => (len, 0),
+          col if col < len || (block.append && col == len) => (col, 0),
+          col if block.append => (len, col - len),
+          // I skips rows too short to reach the block
+          _ => continue,
+        };
+        self.insert_text(start + col, &format!("{}{}", " ".repeat(pad), typed));
+      }
+      self.dirty = true;
+      self.retokenize();
+    }
+    self.set_cursor(block.start);
+    true
+  }
+
+  // Content changes go through insert_text and remove_text so that
+  // retokenize only has to look at what changed
+  fn insert_text(&mut self, at: usize, text: &str) {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(tree) = &mut self.tree {
+      tree.edit(&self.content, at, at, text);
+    }
+    self.content.insert(at, text);
+    self.note_edit(at, 0, text.chars().count());
+  }
+
+  fn remove_text(&mut self, from: usize, to: usize) {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(tree) = &mut self.tree {
+      tree.edit(&self.content, from, to, "");
+    }
+    self.content.remove(from..to);
+    self.note_edit(from, to - from, 0);
+  }
+
+  fn note_edit(&mut self, at: usize, removed: usize, inserted: usize) {
+    self.moves.push(Edit { from: at, old_to: at + removed, new_to: at + inserted });
+    self.uncommitted = Some(match self.uncommitted {
+      Some(edit) => edit.merge(at, removed, inserted),
+      None => Edit { from: at, old_to: at + removed, new_to: at + inserted },
+    });
+    self.edited = Some(match self.edited {
+      Some(edit) => edit.merge(at, removed, inserted),
+      None => Edit { from: at, old_to: at + removed, new_to: at + inserted },
+    });
+  }
+
+  // Brings the token list and highlighting up to date with the edits
+  // since the last call, or with everything when the content was swapped
+  fn retokenize(&mut self) {
+    match self.edited.take() {
+      Some(edit) => {
+        self.relex(edit);
+        self.syntax.edit(&self.content, edit.from, edit.new_to);
+        #[cfg(feature = "tree-sitter")]
+        if let Some(tree) = &mut self.tree {
+          tree.parse(&self.content);
+        }
+        // Folds the edit went into are dropped, the ones after it move
+        let delta = edit.new_to as isize - edit.old_to as isize;
+        self.folds.retain_mut(|fold| match *fold {
+          (_, last) if last < edit.from => true,
+          (first, last) if first >= edit.old_to => {
+            *fold = ((first as isize + delta) as usize, (last as isize + delta) as usize);
+            true
+          },
+          _ => false,
+        });
+      },
+      None => {
+        self.token_list = Lexer::from(self.content.to_string().as_str()).collect();
+        self.syntax.update(&self.content);
+        #[cfg(feature = "tree-sitter")]
+        if let Some(tree) = &mut self.tree {
+          tree.reset(&self.content);
+        }
+        self.folds.clear();
+      },
+    }
+  }
+
+  // Lexes again from the start of the token before the edit until a new
+  // token ends where an old one after the edit started. The tokens from
+  // there on are the old ones moved by the length difference.
+  fn relex(&mut self, edit: Edit) {
+    let delta = edit.new_to as isize - edit.old_to as isize;
+    let first = self.token_list.partition_point(|token| token.position().1 + 1 < edit.from);
+    let start = self.token_list.get(first).map_or(0, |token| token.position().0.min(edit.from));
+    let len = self.content.len_chars();
+    // Text is taken from the rope in growing windows, a token ending
+    // at the window's end may go on past it
+    let mut window = (edit.new_to + 256).min(len);
+    loop {
+      let text = self.content.slice(start..window).to_string();
+      let mut tokens = Vec::new();
+      for token in Lexer::from(text.as_str()) {
+        let (first_char, last_char) = token.position();
+        let end = start + last_char + 1;
+        tokens.push(Token::new(token.kind(), (start + first_char, end - 1)));
+        if end < edit.new_to || end == window {
+          continue
+        }
+        let old = (end as isize - delta) as usize;
+        if let Ok(i) = self.token_list[first..].binary_search_by_key(&old, |token| token.position().0) {
+          let resync = first + i;
+          let count = tokens.len();
+          self.token_list.splice(first..resync, tokens);
+          for token in &mut self.token_list[first + count..] {
+            let (from, to) = token.position();
+            *token = Token::new(token.kind(), ((from as isize + delta) as usize, (to as isize + delta) as usize));
+          }
+          return
+        }
+      }
+      if window == len {
+        self.token_list.truncate(first);
+        self.token_list.extend(tokens);
+        return
+      }
+      window = (start + 2 * (window - start)).min(len);
+    }
+  }
+
+  // Takes the edits since the last call, as a map from positions before
+  // them to positions after, for cursors of other panes on the buffer
+  pub fn take_moves(&mut self) -> impl Fn(usize) -> usize {
+    let moves = std::mem::take(&mut self.moves);
+    let len = self.content.len_chars();
+    move |pos| moves.iter().fold(pos, |pos, edit| edit.shift(pos)).min(len)
+  }
+
+  // Row n without its line ending
+  pub fn nth(&self, n: usize) -> Cow<'_, str> {
+    if n >= self.content.len_lines() {
+      return Cow::Borrowed("")
+    }
+    let line = self.content.line(n);
+    let len = line.len_chars();
+    match len > 0 && line.char(len - 1) == '\n' {
+      true  => line.slice(..len - 1).into(),
+      false => line.into(),
+    }
   }
 
   pub fn is_empty(&self) -> bool {
-    self.content.is_empty()
+    self.content.len_chars() == 0
+  }
+}
+
+// Chars from..old_to before the edits stand at from..new_to after them
+#[derive(Debug, Clone, Copy)]
+struct Edit {
+  from: usize,
+  old_to: usize,
+  new_to: usize,
+}
+
+impl Edit {
+  // Where `pos` ends up, positions in the removed stretch go to its end
+  fn shift(&self, pos: usize) -> usize {
+    match pos {
+      pos if pos >= self.old_to => pos - self.old_to + self.new_to,
+      pos if pos > self.from => pos.min(self.new_to),
+      pos => pos,
+    }
+  }
+
+  // Widens the stretch by `removed` chars at `at` replaced with
+  // `inserted` ones
+  fn merge(self, at: usize, removed: usize, inserted: usize) -> Edit {
+    let delta = self.new_to as isize - self.old_to as isize + inserted as isize - removed as isize;
+    let end = match self.new_to {
+      end if end >= at + removed => end - removed + inserted,
+      end => end.min(at),
+    };
+    let new_to = end.max(at + inserted);
+    Edit {
+      from: self.from.min(at),
+      old_to: (new_to as isize - delta) as usize,
+      new_to,
+    }
+  }
+}
+
+// Visual-block I, A and c in progress. `col` is usize::MAX when
+// appending after $.
+#[derive(Debug, Clone, Copy)]
+struct BlockInsert {
+  first: usize,
+  last: usize,
+  col: usize,
+  // Where the typed text starts on the first row
+  start: usize,
+  append: bool,
+}
+
+// Text an operator took out of the buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Yanked {
+  pub text: String,
+  pub kind: YankKind,
+}
+
+impl Yanked {
+  // "A to "Z, adding a linewise piece makes the whole register linewise
+  pub fn append(self, other: &Yanked) -> Yanked {
+    let mut text = self.text;
+    let kind = match (self.kind, other.kind) {
+      (YankKind::Char, YankKind::Char) => YankKind::Char,
+      (YankKind::Line, _) | (_, YankKind::Line) => {
+        if !text.ends_with('\n') {
+          text.push('\n');
+        }
+        YankKind::Line
+      },
+      _ => {
+        text.push('\n');
+        YankKind::Block
+      },
+    };
+    text.push_str(&other.text);
+    if kind == YankKind::Line && !text.ends_with('\n') {
+      text.push('\n');
+    }
+    Yanked { text, kind }
   }
 }
 
-pub enum Seek{
-  Char(char),
-  Word,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YankKind {
+  Char,
+  // Whole rows, the text always ends with a line break
+  Line,
+  // One piece per row, joined with line breaks
+  Block,
 }
 
 impl Display for Buffer {
@@ -255,7 +1747,7 @@ impl Display for Buffer {
     let mut formatted = String::new();
     for (idx, c) in self.content
       .chars()
-      .enumerate() 
+      .enumerate()
     {
       if self.cursor == idx {
         formatted.push('|')
@@ -264,4 +1756,108 @@ impl Display for Buffer {
     }
     write!(f, "Cursor: {}, \n{}\n", self.cursor, formatted)
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::search::Syntax;
+
+  // Every match start in the buffer, what search has to agree with
+  fn starts(buffer: &Buffer, pattern: &Pattern) -> Vec<usize> {
+    let text = buffer.content.to_string();
+    pattern.find_all(&text).into_iter().map(|m| buffer.content.byte_to_char(m.start)).collect()
+  }
+
+  #[test]
+  fn search_matches_a_full_scan() {
+    // Long enough for several windows, with matches across line breaks
+    let text: String = (0..2000)
+      .map(|i| match i % 7 {
+        _ if i % 300 == 254 => "aa\nbb\ncc\n".to_string(),
+        0 => "foo bar\n".to_string(),
+        3 => "barfoo\n".to_string(),
+        _ => format!("line {}\n", i),
+      })
+      .collect();
+    let buffer = Buffer::new(&text);
+    let len = buffer.content.len_chars();
+    for pattern in ["foo", "^bar", "bar$", "r\\nline", "line 1999", "1234", "aa\\nbb\\ncc", "a\\sb"] {
+      let compiled = Pattern::new(pattern, Syntax::Vim, false).unwrap();
+      let starts = starts(&buffer, &compiled);
+      for from in (0..len).step_by(97).chain([0, len - 1]) {
+        let forward = starts.iter().find(|at| **at > from).map(|at| (*at, false))
+          .or(starts.first().map(|at| (*at, true)));
+        let backward = starts.iter().rev().find(|at| **at < from).map(|at| (*at, false))
+          .or(starts.last().map(|at| (*at, true)));
+        assert_eq!(buffer.search(&compiled, from, true), forward, "{} from {}", pattern, from);
+        assert_eq!(buffer.search(&compiled, from, false), backward, "{} from {}", pattern, from);
+      }
+    }
+  }
+
+  // Random edits of every kind keep the tokens and highlighting the same
+  // as lexing the whole buffer again
+  #[test]
+  fn relex_matches_a_full_lex() {
+    use crate::editor::Editor;
+    use crate::layout::Area;
+    let pieces = [
+      "/*", "*/", "\"", "r#\"", "\"#", "\n", "foo", " ", "12", "'a", "'x'", "//", "é", "😀", "\n\n", "fn", "x\ny",
+      "#", "```", "[a]", "= 1",
+    ];
+    let keys = [
+      "x", "dw", "dd", "J", "p", "u", "yyp", "Vj>", "Vj<", "\u{16}jIab\u{1b}", "\u{16}jlc\u{1b}", "gUiw", "r\r", "3\u{12}",
+    ];
+    let mut seed: u64 = 12345;
+    let mut random = |n: usize| {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      (seed % n as u64) as usize
+    };
+    let text = "fn main() {\n  /* a */ let s = \"x\";\n}\n# t\n```\nq\n```\n[table]\nkey = 'v'";
+    for language in [Language::Rust, Language::C, Language::Python, Language::Json, Language::Markdown, Language::Toml] {
+      let mut editor = Editor::new(Buffer::new(text), Area::new(0, 0, 800, 480), (10, 20), None);
+      editor.buffers.current_mut().set_language(language);
+      for step in 0..300 {
+        let len = editor.buffers.current().content.len_chars();
+        let typed = match random(4) {
+          0 | 1 => {
+            editor.buffers.current_mut().set_cursor(random(len + 1));
+            format!("i{}\u{1b}", pieces[random(pieces.len())].replace('\n', "\r"))
+          },
+          2 if len > 0 => {
+            editor.buffers.current_mut().set_cursor(random(len));
+            keys[random(keys.len())].to_string()
+          },
+          _ => ":%s/o/\\r/g\r".to_string(),
+        };
+        for key in typed.chars() {
+          editor.feed(key);
+        }
+        let buffer = editor.buffers.current();
+        let content = buffer.content.to_string();
+        let tokens: Vec<Token> = Lexer::from(content.as_str()).collect();
+        assert!(buffer.token_list == tokens, "{:?} step {} after {:?}: tokens differ", language, step, typed);
+        let mut fresh = Buffer::new(&content);
+        fresh.set_language(language);
+        for row in 0..buffer.height() {
+          assert_eq!(buffer.spans(row), fresh.spans(row), "{:?} step {} after {:?}: row {}", language, step, typed, row);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn search_wraps_once() {
+    let buffer = Buffer::new("foo\nbar\nbaz");
+    let pattern = Pattern::new("ba", Syntax::Vim, false).unwrap();
+    assert_eq!(buffer.search(&pattern, 0, true), Some((4, false)));
+    assert_eq!(buffer.search(&pattern, 8, true), Some((4, true)));
+    assert_eq!(buffer.search(&pattern, 4, false), Some((8, true)));
+    let missing = Pattern::new("qux", Syntax::Vim, false).unwrap();
+    assert_eq!(buffer.search(&missing, 3, true), None);
+    assert_eq!(buffer.search(&missing, 3, false), None);
+  }
+}
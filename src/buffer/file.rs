@@ -0,0 +1,176 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// File
+//------------------------------------------------------
+//------------------------------------------------------
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use ropey::Rope;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+  Lf,
+  CrLf,
+}
+
+impl LineEnding {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      LineEnding::Lf   => "\n",
+      LineEnding::CrLf => "\r\n",
+    }
+  }
+}
+
+// Where a buffer came from and how to write it back. The buffer itself
+// always holds "\n" line endings without the final one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+  pub path: Option<PathBuf>,
+  pub line_ending: LineEnding,
+  pub eol: bool,
+}
+
+impl FileInfo {
+  pub fn new(path: Option<PathBuf>) -> Self {
+    FileInfo {
+      path,
+      line_ending: LineEnding::Lf,
+      eol: true,
+    }
+  }
+
+  // A missing file is not an error, it is a new file
+  pub fn read(path: &Path) -> Result<(FileInfo, String), String> {
+    let raw = match fs::read_to_string(path) {
+      Ok(raw) => raw,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        return Ok((FileInfo::new(Some(path.to_path_buf())), String::new()))
+      },
+      Err(e) => return Err(format!("\"{}\": {}", path.display(), e)),
+    };
+    // CRLF only when every line uses it, a mixed file keeps its "\r"s in
+    // the rows and is written back as it was
+    let crlf = raw.matches('\n').count() == raw.matches("\r\n").count();
+    let line_ending = match raw.contains('\n') && crlf {
+      true  => LineEnding::CrLf,
+      false => LineEnding::Lf,
+    };
+    let mut text = match line_ending {
+      LineEnding::CrLf => raw.replace("\r\n", "\n"),
+      LineEnding::Lf   => raw,
+    };
+    let eol = text.ends_with('\n');
+    if eol {
+      text.pop();
+    }
+    Ok((FileInfo { path: Some(path.to_path_buf()), line_ending, eol }, text))
+  }
+
+  // Writes to a temporary file next to the target and renames it over,
+  // so a failed write never leaves a truncated file behind.
+  pub fn write(&self, content: &Rope, path: &Path) -> Result<usize, String> {
+    let err = |e: std::io::Error| format!("\"{}\": {}", path.display(), e);
+    let name = path
+      .file_name()
+      .ok_or(format!("\"{}\": not a file", path.display()))?
+      .to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.tmp", name));
+
+    let result = self.write_to(content, &tmp)
+      .and_then(|bytes| {
+        if let Ok(meta) = fs::metadata(path) {
+          fs::set_permissions(&tmp, meta.permissions())?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(bytes)
+      });
+    if result.is_err() {
+      let _ = fs::remove_file(&tmp);
+    }
+    result.map_err(err)
+  }
+
+  // The rope is written as it is, only its "\n"s become the file's
+  // line ending
+  fn write_to(&self, content: &Rope, path: &Path) -> std::io::Result<usize> {
+    let ending = self.line_ending.as_str();
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    let mut bytes = 0;
+    for chunk in content.chunks() {
+      for (idx, piece) in chunk.split('\n').enumerate() {
+        if idx > 0 {
+          file.write_all(ending.as_bytes())?;
+          bytes += ending.len();
+        }
+        file.write_all(piece.as_bytes())?;
+        bytes += piece.len();
+      }
+    }
+    // A file of a single line ending reads as empty text, the ending is
+    // still written back
+    if self.eol {
+      file.write_all(ending.as_bytes())?;
+      bytes += ending.len();
+    }
+    file.flush()?;
+    file.get_ref().sync_all()?;
+    Ok(bytes)
+  }
+
+  pub fn name(&self) -> String {
+    match &self.path {
+      Some(path) => path.display().to_string(),
+      None => "[No Name]".to_string(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Reads `raw` from a file and writes it back through a rope
+  fn round_trip(name: &str, raw: &[u8]) -> Vec<u8> {
+    let path = std::env::temp_dir().join(format!("editor-file-{}-{}", std::process::id(), name));
+    fs::write(&path, raw).unwrap();
+    let (info, text) = FileInfo::read(&path).unwrap();
+    info.write(&Rope::from_str(&text), &path).unwrap();
+    let written = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    written
+  }
+
+  #[test]
+  fn unicode_breaks_are_kept() {
+    let raw = "a\x0cb\nc\rd\x0be\u{85}f\u{2028}g\u{2029}h\n".as_bytes();
+    assert_eq!(round_trip("unicode", raw), raw);
+  }
+
+  #[test]
+  fn line_endings_are_kept() {
+    for (name, raw) in [
+      ("lf", "a\nb\n"),
+      ("crlf", "a\r\nb\r\n"),
+      ("mixed", "a\r\nb\nc\r\n"),
+      ("noeol", "a\r\nb"),
+      ("empty", ""),
+      ("newline", "\n"),
+      ("crlf-newline", "\r\n"),
+    ] {
+      assert_eq!(round_trip(name, raw.as_bytes()), raw.as_bytes(), "{}", name);
+    }
+  }
+
+  #[test]
+  fn crlf_is_read_as_lf() {
+    let path = std::env::temp_dir().join(format!("editor-file-{}-read", std::process::id()));
+    fs::write(&path, "a\r\nb\r\n").unwrap();
+    let (info, text) = FileInfo::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(info.line_ending, LineEnding::CrLf);
+    assert!(info.eol);
+    assert_eq!(text, "a\nb");
+  }
+}
@@ -12,7 +12,7 @@ pub struct Lexer<'a> {
   char_cursor: usize,
 }
 impl<'a> Lexer<'a> {
-  pub fn new(input: &'a str) -> Result<Lexer, String> {
+  pub fn new(input: &'a str) -> Result<Lexer<'a>, String> {
     Ok(Lexer { 
       source: input, 
       byte_cursor: 0,
@@ -20,8 +20,14 @@ impl<'a> Lexer<'a> {
     })
   }
 
+  // Text from the cursor on, the byte cursor always sits on a char
+  // boundary
+  fn rest(&self) -> &'a str {
+    &self.source[self.byte_cursor..]
+  }
+
   fn char_at_cursor(&self) -> Option<char> {
-    self.source.chars().nth(self.char_cursor)
+    self.rest().chars().next()
   }
 }
 
@@ -40,14 +46,10 @@ impl Iterator for Lexer<'_> {
       None => return None,
     };
 
-    let literal = self.source
+    let (raw_count, char_count) = self.rest()
       .chars()
-      .skip(self.char_cursor)
       .take_while(|c| get_kind(*c) == token_kind)
-      .collect::<String>();
-
-    let raw_count = literal.len();
-    let char_count = literal.chars().count();
+      .fold((0, 0), |(raw, chars), c| (raw + c.len_utf8(), chars + 1));
 
     self.byte_cursor += raw_count;
     self.char_cursor += char_count;
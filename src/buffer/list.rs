@@ -0,0 +1,106 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// BufferList
+//------------------------------------------------------
+//------------------------------------------------------
+use std::path::Path;
+use super::buffer::Buffer;
+
+// Buffers keep the number they were opened with for their whole
+// lifetime, like vim's buffer numbers.
+pub struct BufferList {
+  buffers: Vec<(usize, Buffer)>,
+  current: usize,
+  next_id: usize,
+}
+
+impl BufferList {
+  pub fn new(buffer: Buffer) -> Self {
+    BufferList {
+      buffers: vec![(1, buffer)],
+      current: 0,
+      next_id: 2,
+    }
+  }
+
+  pub fn current(&self) -> &Buffer {
+    &self.buffers[self.current].1
+  }
+
+  pub fn current_mut(&mut self) -> &mut Buffer {
+    &mut self.buffers[self.current].1
+  }
+
+  pub fn current_id(&self) -> usize {
+    self.buffers[self.current].0
+  }
+
+  pub fn get(&self, id: usize) -> Option<&Buffer> {
+    self.position(id).map(|idx| &self.buffers[idx].1)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (usize, &Buffer)> {
+    self.buffers.iter().map(|(id, buffer)| (*id, buffer))
+  }
+
+  // Adds a buffer, makes it current and returns its number
+  pub fn add(&mut self, buffer: Buffer) -> usize {
+    let id = self.next_id;
+    self.next_id += 1;
+    self.buffers.push((id, buffer));
+    self.current = self.buffers.len() - 1;
+    id
+  }
+
+  // Switches to the buffer already holding `path`, or opens it
+  pub fn open(&mut self, path: &Path) -> Result<usize, String> {
+    let existing = self.buffers
+      .iter()
+      .position(|(_, buffer)| buffer.file.path.as_deref() == Some(path));
+    match existing {
+      Some(idx) => {
+        self.current = idx;
+        Ok(self.buffers[idx].0)
+      },
+      None => Ok(self.add(Buffer::open(path)?)),
+    }
+  }
+
+  pub fn switch(&mut self, id: usize) -> Result<(), String> {
+    self.current = self.position(id).ok_or(format!("Buffer {} does not exist", id))?;
+    Ok(())
+  }
+
+  pub fn next(&mut self) {
+    self.current = (self.current + 1) % self.buffers.len();
+  }
+
+  pub fn prev(&mut self) {
+    self.current = (self.current + self.buffers.len() - 1) % self.buffers.len();
+  }
+
+  // The list is never left empty, deleting the last buffer leaves a
+  // fresh one in its place.
+  pub fn remove(&mut self, id: usize, force: bool) -> Result<(), String> {
+    let idx = self.position(id).ok_or(format!("Buffer {} does not exist", id))?;
+    if !force && self.buffers[idx].1.is_dirty() {
+      return Err(format!("No write since last change for buffer {} (add ! to override)", id))
+    }
+    self.buffers.remove(idx);
+    if self.buffers.is_empty() {
+      self.add(Buffer::new(""));
+    } else if self.current > idx || self.current == self.buffers.len() {
+      self.current -= 1;
+    }
+    Ok(())
+  }
+
+  // First buffer with unsaved changes
+  pub fn first_dirty(&self) -> Option<(usize, &Buffer)> {
+    self.iter().find(|(_, buffer)| buffer.is_dirty())
+  }
+
+  fn position(&self, id: usize) -> Option<usize> {
+    self.buffers.iter().position(|(bid, _)| *bid == id)
+  }
+}
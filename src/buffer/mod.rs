@@ -1,4 +1,10 @@
+#[allow(clippy::module_inception)]
 pub mod buffer;
 pub mod token;
 pub mod lexer;
+pub mod file;
+pub mod list;
+pub mod syntax;
+#[cfg(feature = "tree-sitter")]
+pub mod tree;
 
@@ -0,0 +1,775 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Syntax
+//------------------------------------------------------
+//------------------------------------------------------
+use std::path::Path;
+use ropey::Rope;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+  Plain,
+  Rust,
+  C,
+  Python,
+  Json,
+  Markdown,
+  Toml,
+}
+
+impl Language {
+  pub fn from_path(path: &Path) -> Self {
+    if path.file_name().is_some_and(|name| name == "Cargo.lock") {
+      return Language::Toml
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("rs") => Language::Rust,
+      Some("c" | "h") => Language::C,
+      Some("py" | "pyw" | "pyi") => Language::Python,
+      Some("json") => Language::Json,
+      Some("md" | "markdown") => Language::Markdown,
+      Some("toml") => Language::Toml,
+      _ => Language::Plain,
+    }
+  }
+
+  // For :set filetype=
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "text" | "" => Some(Language::Plain),
+      "rust" => Some(Language::Rust),
+      "c" => Some(Language::C),
+      "python" => Some(Language::Python),
+      "json" => Some(Language::Json),
+      "markdown" => Some(Language::Markdown),
+      "toml" => Some(Language::Toml),
+      _ => None,
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Language::Plain => "text",
+      Language::Rust => "rust",
+      Language::C => "c",
+      Language::Python => "python",
+      Language::Json => "json",
+      Language::Markdown => "markdown",
+      Language::Toml => "toml",
+    }
+  }
+
+  fn rules(&self) -> Option<&'static Rules> {
+    match self {
+      Language::Rust => Some(&RUST),
+      Language::C => Some(&C),
+      Language::Python => Some(&PYTHON),
+      Language::Json => Some(&JSON),
+      Language::Toml => Some(&TOML),
+      Language::Plain | Language::Markdown => None,
+    }
+  }
+}
+
+// What a stretch of text is, the theme gives each one a colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+  Text,
+  Keyword,
+  Type,
+  String,
+  Comment,
+  Number,
+  Operator,
+  Heading,
+}
+
+// Columns from..to of a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+  pub from: usize,
+  pub to: usize,
+  pub class: Class,
+}
+
+// What is still open at the end of a row. Rows are lexed on their own
+// starting from the state the row above ended in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum State {
+  #[default]
+  Normal,
+  // Block comment and how deep it is nested
+  Comment(usize),
+  String(Quote),
+  // Markdown code block and the fence that opened it
+  Fence(char, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quote {
+  char: char,
+  // 3 for """ and ''', 1 otherwise
+  count: usize,
+  // #s closing a Rust raw string
+  hashes: usize,
+  // \ escapes nothing
+  raw: bool,
+}
+
+//------------------------------------------------------
+// Rules
+//------------------------------------------------------
+struct Rules {
+  keywords: &'static [&'static str],
+  types: &'static [&'static str],
+  constants: &'static [&'static str],
+  line_comment: Option<&'static str>,
+  block_comment: Option<(&'static str, &'static str)>,
+  nested_comments: bool,
+  quotes: &'static str,
+  // Quotes whose strings take no escapes, TOML's '
+  raw_quotes: &'static str,
+  triple_quotes: bool,
+  // Strings without their closing quote go on in the next row
+  multiline_strings: bool,
+  // Capitalized words are types and all caps ones constants
+  capitalized: bool,
+  operators: &'static str,
+  // A string followed by this is a key, JSON's : and TOML's =
+  key: Option<char>,
+}
+
+const RUST: Rules = Rules {
+  keywords: &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+    "union", "unsafe", "use", "where", "while", "yield",
+  ],
+  types: &[
+    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize",
+    "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64",
+  ],
+  constants: &["true", "false"],
+  line_comment: Some("//"),
+  block_comment: Some(("/*", "*/")),
+  nested_comments: true,
+  quotes: "\"",
+  raw_quotes: "",
+  triple_quotes: false,
+  multiline_strings: true,
+  capitalized: true,
+  operators: "+-*/%=<>!&|^~?:;",
+  key: None,
+};
+
+const C: Rules = Rules {
+  keywords: &[
+    "auto", "break", "case", "const", "continue", "default", "do", "else", "enum", "extern",
+    "for", "goto", "if", "inline", "register", "restrict", "return", "sizeof", "static",
+    "struct", "switch", "typedef", "union", "volatile", "while",
+  ],
+  types: &[
+    "void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "bool",
+    "_Bool", "size_t", "ssize_t", "ptrdiff_t", "intptr_t", "uintptr_t", "FILE",
+    "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+  ],
+  constants: &["NULL", "true", "false"],
+  line_comment: Some("//"),
+  block_comment: Some(("/*", "*/")),
+  nested_comments: false,
+  quotes: "\"'",
+  raw_quotes: "",
+  triple_quotes: false,
+  multiline_strings: false,
+  capitalized: true,
+  operators: "+-*/%=<>!&|^~?:;",
+  key: None,
+};
+
+const PYTHON: Rules = Rules {
+  keywords: &[
+    "and", "as", "assert", "async", "await", "break", "case", "class", "continue", "def",
+    "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "match", "nonlocal", "not", "or", "pass", "raise", "return",
+    "try", "while", "with", "yield",
+  ],
+  types: &[
+    "bool", "bytearray", "bytes", "complex", "dict", "float", "frozenset", "int", "list",
+    "object", "set", "str", "tuple", "type",
+  ],
+  constants: &["True", "False", "None"],
+  line_comment: Some("#"),
+  block_comment: None,
+  nested_comments: false,
+  quotes: "\"'",
+  raw_quotes: "",
+  triple_quotes: true,
+  multiline_strings: false,
+  capitalized: true,
+  operators: "+-*/%=<>!&|^~@:",
+  key: None,
+};
+
+const JSON: Rules = Rules {
+  keywords: &[],
+  types: &[],
+  constants: &["true", "false", "null"],
+  line_comment: None,
+  block_comment: None,
+  nested_comments: false,
+  quotes: "\"",
+  raw_quotes: "",
+  triple_quotes: false,
+  multiline_strings: false,
+  capitalized: false,
+  operators: "",
+  key: Some(':'),
+};
+
+const TOML: Rules = Rules {
+  keywords: &[],
+  types: &[],
+  constants: &["true", "false", "inf", "nan"],
+  line_comment: Some("#"),
+  block_comment: None,
+  nested_comments: false,
+  quotes: "\"'",
+  raw_quotes: "'",
+  triple_quotes: true,
+  multiline_strings: false,
+  capitalized: false,
+  operators: "=",
+  key: Some('='),
+};
+
+//------------------------------------------------------
+// Highlighter
+//------------------------------------------------------
+// Spans of every row of a buffer. Rows of plain text have none.
+pub struct Highlighter {
+  language: Language,
+  lines: Vec<Line>,
+}
+
+struct Line {
+  // State the row starts in
+  state: State,
+  spans: Vec<Span>,
+}
+
+impl Highlighter {
+  pub fn new(language: Language, content: &Rope) -> Self {
+    let mut highlighter = Highlighter { language, lines: Vec::new() };
+    highlighter.update(content);
+    highlighter
+  }
+
+  pub fn language(&self) -> Language {
+    self.language
+  }
+
+  pub fn set_language(&mut self, language: Language, content: &Rope) {
+    self.language = language;
+    self.update(content);
+  }
+
+  // Lexes every row again
+  pub fn update(&mut self, content: &Rope) {
+    self.lines.clear();
+    if self.language == Language::Plain {
+      return
+    }
+    let mut state = State::Normal;
+    for line in content.lines() {
+      let row: Vec<char> = line.chars().filter(|c| *c != '\n').collect();
+      let (spans, next) = lex_row(self.language, &row, state);
+      self.lines.push(Line { state, spans });
+      state = next;
+    }
+  }
+
+  // Lexes again from the first row of an edit that ended at char `to`.
+  // Past the edited rows it stops at the first row that starts in the
+  // same state as before, the rows from there on are kept as they were.
+  pub fn edit(&mut self, content: &Rope, from: usize, to: usize) {
+    if self.language == Language::Plain {
+      return
+    }
+    let first = content.char_to_line(from);
+    let last = content.char_to_line(to);
+    // Rows after the edit keep their lines, moved by the change in rows
+    let old_last = (last + self.lines.len()).checked_sub(content.len_lines());
+    let Some(old_last) = old_last.filter(|old_last| first <= *old_last && *old_last < self.lines.len()) else {
+      return self.update(content)
+    };
+    let mut state = self.lines[first].state;
+    self.lines.splice(first..=old_last, (first..=last).map(|_| Line { state, spans: Vec::new() }));
+    for row in first..self.lines.len() {
+      if row > last && self.lines[row].state == state {
+        break
+      }
+      let chars: Vec<char> = content.line(row).chars().filter(|c| *c != '\n').collect();
+      let (spans, next) = lex_row(self.language, &chars, state);
+      self.lines[row] = Line { state, spans };
+      state = next;
+    }
+  }
+
+  pub fn spans(&self, row: usize) -> &[Span] {
+    self.lines.get(row).map_or(&[], |line| &line.spans)
+  }
+}
+
+// Spans covering `row` lexed from `state`, and the state the next row
+// starts in
+fn lex_row(language: Language, row: &[char], state: State) -> (Vec<Span>, State) {
+  let mut lexer = RowLexer {
+    language,
+    rules: language.rules(),
+    chars: row,
+    spans: Vec::new(),
+    state: State::Normal,
+  };
+  let state = match language {
+    Language::Plain => State::Normal,
+    Language::Markdown => lexer.markdown(state),
+    _ => lexer.code(state),
+  };
+  lexer.push(row.len(), row.len(), Class::Text);
+  (lexer.spans, state)
+}
+
+//------------------------------------------------------
+// RowLexer
+//------------------------------------------------------
+struct RowLexer<'a> {
+  language: Language,
+  rules: Option<&'static Rules>,
+  chars: &'a [char],
+  spans: Vec<Span>,
+  // Set when a comment or string runs past the end of the row
+  state: State,
+}
+
+impl RowLexer<'_> {
+  // Adds a span, the text between it and the last one is plain
+  fn push(&mut self, from: usize, to: usize, class: Class) {
+    let end = self.spans.last().map_or(0, |span| span.to);
+    if from > end {
+      self.push(end, from, Class::Text);
+    }
+    if to <= from {
+      return
+    }
+    match self.spans.last_mut() {
+      Some(last) if last.class == class && last.to == from => last.to = to,
+      _ => self.spans.push(Span { from, to, class }),
+    }
+  }
+
+  fn starts_with(&self, at: usize, s: &str) -> bool {
+    s.chars().enumerate().all(|(i, c)| self.chars.get(at + i) == Some(&c))
+  }
+
+  fn find(&self, from: usize, s: &str) -> Option<usize> {
+    (from..self.chars.len()).find(|at| self.starts_with(*at, s))
+  }
+
+  // How many `c` follow each other from `at`
+  fn run(&self, at: usize, c: char) -> usize {
+    self.chars.iter().skip(at).take_while(|x| **x == c).count()
+  }
+
+  fn is_word(&self, c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || (self.language == Language::Toml && c == '-')
+  }
+
+  //------------------------------------------------------
+  // Code
+  //------------------------------------------------------
+  fn code(&mut self, state: State) -> State {
+    let Some(rules) = self.rules else {
+      return State::Normal
+    };
+    let len = self.chars.len();
+    let mut at = match state {
+      State::Comment(depth) => self.comment(0, 0, depth),
+      State::String(quote) => self.string(0, 0, quote),
+      _ => 0,
+    };
+    let first = self.chars.iter().position(|c| !c.is_whitespace());
+    while at < len && self.state == State::Normal {
+      let c = self.chars[at];
+      if rules.line_comment.is_some_and(|open| self.starts_with(at, open)) {
+        self.push(at, len, Class::Comment);
+        break
+      }
+      if let Some((open, _)) = rules.block_comment.filter(|(open, _)| self.starts_with(at, open)) {
+        at = self.comment(at, at + open.chars().count(), 1);
+        continue
+      }
+      if Some(at) == first {
+        if let Some(end) = self.row_start(at) {
+          at = end;
+          continue
+        }
+      }
+      if let Some((from, quote)) = self.open_quote(at, false) {
+        at = self.string(at, from, quote);
+        continue
+      }
+      at = match c {
+        '\'' if self.language == Language::Rust => self.rust_quote(at, at),
+        c if c.is_ascii_digit() => self.number(at),
+        c if c.is_alphabetic() || c == '_' => self.word(at),
+        c => {
+          if rules.operators.contains(c) {
+            self.push(at, at + 1, Class::Operator);
+          }
+          at + 1
+        },
+      };
+    }
+    self.state
+  }
+
+  // Lexes a block comment from `from`, `start` being where it began.
+  // Returns where it ends.
+  fn comment(&mut self, start: usize, from: usize, depth: usize) -> usize {
+    let Some((open, close)) = self.rules.and_then(|rules| rules.block_comment) else {
+      return from
+    };
+    let nested = self.rules.is_some_and(|rules| rules.nested_comments);
+    let mut depth = depth;
+    let mut at = from;
+    while at < self.chars.len() {
+      if nested && self.starts_with(at, open) {
+        depth += 1;
+        at += open.len();
+      } else if self.starts_with(at, close) {
+        depth -= 1;
+        at += close.len();
+        if depth == 0 {
+          self.push(start, at, Class::Comment);
+          return at
+        }
+      } else {
+        at += 1;
+      }
+    }
+    self.push(start, at, Class::Comment);
+    self.state = State::Comment(depth);
+    at
+  }
+
+  // The string opening at `at` and where its text starts. With `raw`
+  // a Rust raw string may put #s before the quote.
+  fn open_quote(&self, at: usize, raw: bool) -> Option<(usize, Quote)> {
+    let rules = self.rules?;
+    let hashes = match raw && self.language == Language::Rust {
+      true  => self.run(at, '#'),
+      false => 0,
+    };
+    let c = *self.chars.get(at + hashes)?;
+    if !rules.quotes.contains(c) {
+      return None
+    }
+    let count = match rules.triple_quotes && self.run(at + hashes, c) >= 3 {
+      true  => 3,
+      false => 1,
+    };
+    let quote = Quote {
+      char: c,
+      count,
+      hashes,
+      raw: raw || rules.raw_quotes.contains(c),
+    };
+    Some((at + hashes + count, quote))
+  }
+
+  // Lexes a string from `from`, `start` being where it began. Returns
+  // where it ends.
+  fn string(&mut self, start: usize, from: usize, quote: Quote) -> usize {
+    let len = self.chars.len();
+    let close = format!("{}{}", quote.char.to_string().repeat(quote.count), "#".repeat(quote.hashes));
+    // A \ ending the row carries the string over
+    let mut continued = false;
+    let mut at = from;
+    while at < len {
+      if !quote.raw && self.chars[at] == '\\' {
+        continued = at + 1 == len;
+        at += 2;
+      } else if self.starts_with(at, &close) {
+        let end = at + close.chars().count();
+        let key = self.rules.and_then(|rules| rules.key).is_some_and(|key| {
+          self.chars[end..].iter().find(|c| !c.is_whitespace()) == Some(&key)
+        });
+        self.push(start, end, if key { Class::Type } else { Class::String });
+        return end
+      } else {
+        at += 1;
+      }
+    }
+    self.push(start, len, Class::String);
+    let multiline = self.rules.is_some_and(|rules| rules.multiline_strings);
+    if continued || multiline || quote.count == 3 {
+      self.state = State::String(quote);
+    }
+    len
+  }
+
+  // A Rust char literal or a lifetime at `at`, starting at `start` when
+  // it has a b before it
+  fn rust_quote(&mut self, start: usize, at: usize) -> usize {
+    let end = match (self.chars.get(at + 1), self.chars.get(at + 2)) {
+      (Some('\\'), _) => self.find(at + 3, "'").map_or(self.chars.len(), |close| close + 1),
+      (Some(_), Some('\'')) => at + 3,
+      _ => {
+        let end = at + 1 + self.chars[at + 1..].iter().take_while(|c| self.is_word(**c)).count();
+        self.push(start, end, Class::Type);
+        return end
+      },
+    };
+    self.push(start, end, Class::String);
+    end
+  }
+
+  fn number(&mut self, start: usize) -> usize {
+    let hex = self.chars.get(start + 1).is_some_and(|c| matches!(c, 'x' | 'X'));
+    let digit_at = |at: usize| self.chars.get(at).is_some_and(|c| c.is_ascii_digit());
+    let mut at = start;
+    while let Some(&c) = self.chars.get(at) {
+      let part = match c {
+        c if c.is_alphanumeric() || c == '_' => true,
+        '.' => digit_at(at + 1),
+        '+' | '-' if !hex && matches!(self.chars[at - 1], 'e' | 'E') => digit_at(at + 1),
+        // TOML dates and times
+        '-' | ':' => self.language == Language::Toml && digit_at(at + 1),
+        _ => false,
+      };
+      if !part {
+        break
+      }
+      at += 1;
+    }
+    self.push(start, at, Class::Number);
+    at
+  }
+
+  fn word(&mut self, start: usize) -> usize {
+    let Some(rules) = self.rules else {
+      return start + 1
+    };
+    let end = start + self.chars[start..].iter().take_while(|c| self.is_word(**c)).count();
+    let word: String = self.chars[start..end].iter().collect();
+    if let Some(raw) = self.prefix(&word) {
+      if let Some((from, quote)) = self.open_quote(end, raw) {
+        return self.string(start, from, quote)
+      }
+      if self.language == Language::Rust && word == "b" && self.chars.get(end) == Some(&'\'') {
+        return self.rust_quote(start, end)
+      }
+    }
+    let macro_call = self.language == Language::Rust
+      && self.chars.get(end) == Some(&'!')
+      && self.chars.get(end + 1) != Some(&'=');
+    let capitalized = word.starts_with(|c: char| c.is_uppercase());
+    let class = match word.as_str() {
+      word if rules.keywords.contains(&word) => Class::Keyword,
+      word if rules.constants.contains(&word) => Class::Number,
+      word if rules.types.contains(&word) => Class::Type,
+      _ if macro_call => {
+        self.push(start, end + 1, Class::Keyword);
+        return end + 1
+      },
+      // Bare keys
+      _ if self.language == Language::Toml => Class::Type,
+      word if rules.capitalized && capitalized && word.len() > 1 && !word.contains(char::is_lowercase) => Class::Number,
+      _ if rules.capitalized && capitalized => Class::Type,
+      _ => Class::Text,
+    };
+    self.push(start, end, class);
+    end
+  }
+
+  // Whether `word` can go before a quote and makes a raw string then
+  fn prefix(&self, word: &str) -> Option<bool> {
+    match self.language {
+      Language::Rust => match word {
+        "b" | "c" => Some(false),
+        "r" | "br" | "cr" => Some(true),
+        _ => None,
+      },
+      Language::Python if word.len() <= 2 && word.chars().all(|c| "rRbBfFuU".contains(c)) => {
+        Some(word.contains(['r', 'R']))
+      },
+      Language::C if matches!(word, "L" | "u" | "U" | "u8") => Some(false),
+      _ => None,
+    }
+  }
+
+  // What only counts at the start of a row: C preprocessor lines,
+  // Python decorators and TOML tables
+  fn row_start(&mut self, at: usize) -> Option<usize> {
+    let word_end = |from: usize| from + self.chars[from..].iter().take_while(|c| self.is_word(**c)).count();
+    match (self.language, self.chars[at]) {
+      (Language::C, '#') => {
+        let name = at + 1 + self.chars[at + 1..].iter().take_while(|c| c.is_whitespace()).count();
+        let end = word_end(name);
+        self.push(at, end, Class::Keyword);
+        let directive: String = self.chars[name..end].iter().collect();
+        let path = end + self.chars[end..].iter().take_while(|c| c.is_whitespace()).count();
+        if directive == "include" && self.chars.get(path) == Some(&'<') {
+          if let Some(close) = self.find(path, ">") {
+            self.push(path, close + 1, Class::String);
+            return Some(close + 1)
+          }
+        }
+        Some(end)
+      },
+      (Language::Python, '@') => {
+        let end = word_end(at + 1);
+        self.push(at, end, Class::Keyword);
+        Some(end)
+      },
+      (Language::Toml, '[') => {
+        let close = self.find(at, "]")?;
+        let end = close + self.run(close, ']').min(2);
+        self.push(at, end, Class::Heading);
+        Some(end)
+      },
+      _ => None,
+    }
+  }
+
+  //------------------------------------------------------
+  // Markdown
+  //------------------------------------------------------
+  fn markdown(&mut self, state: State) -> State {
+    let len = self.chars.len();
+    let indent = self.run(0, ' ');
+    match state {
+      State::Fence(c, open) => {
+        self.push(0, len, Class::String);
+        let run = self.run(indent, c);
+        let closed = self.chars[(indent + run).min(len)..].iter().all(|c| c.is_whitespace());
+        return match run >= open && closed {
+          true  => State::Normal,
+          false => state,
+        }
+      },
+      State::Comment(_) => match self.find(0, "-->") {
+        Some(end) => {
+          self.push(0, end + 3, Class::Comment);
+          return self.inline(end + 3)
+        },
+        None => {
+          self.push(0, len, Class::Comment);
+          return state
+        },
+      },
+      _ => {},
+    }
+    let Some(&first) = self.chars.get(indent) else {
+      return State::Normal
+    };
+    let run = self.run(indent, first);
+    match first {
+      '`' | '~' if run >= 3 => {
+        self.push(0, len, Class::String);
+        return State::Fence(first, run)
+      },
+      '#' if run <= 6 && matches!(self.chars.get(indent + run), None | Some(' ')) => {
+        self.push(0, len, Class::Heading);
+        return State::Normal
+      },
+      '>' => {
+        self.push(0, len, Class::Comment);
+        return State::Normal
+      },
+      '-' | '*' | '_' if self.chars.iter().filter(|c| **c != ' ').all(|c| *c == first)
+        && self.chars.iter().filter(|c| **c == first).count() >= 3 => {
+        self.push(0, len, Class::Operator);
+        return State::Normal
+      },
+      _ => {},
+    }
+    let digits = self.chars[indent..].iter().take_while(|c| c.is_ascii_digit()).count();
+    let marker = match first {
+      '-' | '*' | '+' => 1,
+      _ if digits > 0 && matches!(self.chars.get(indent + digits), Some('.' | ')')) => digits + 1,
+      _ => 0,
+    };
+    if marker > 0 && self.chars.get(indent + marker) == Some(&' ') {
+      self.push(indent, indent + marker, Class::Operator);
+      return self.inline(indent + marker)
+    }
+    self.inline(indent)
+  }
+
+  // Code spans, emphasis, links and HTML comments
+  fn inline(&mut self, from: usize) -> State {
+    let len = self.chars.len();
+    let mut at = from;
+    while at < len {
+      let c = self.chars[at];
+      let run = self.run(at, c);
+      at = match c {
+        '\\' => at + 2,
+        '`' => {
+          let fence = "`".repeat(run);
+          let close = (at + run..len).find(|i| self.starts_with(*i, &fence) && self.run(*i, '`') == run);
+          match close {
+            Some(close) => {
+              self.push(at, close + run, Class::String);
+              close + run
+            },
+            None => at + run,
+          }
+        },
+        '<' if self.starts_with(at, "<!--") => match self.find(at + 4, "-->") {
+          Some(end) => {
+            self.push(at, end + 3, Class::Comment);
+            end + 3
+          },
+          None => {
+            self.push(at, len, Class::Comment);
+            return State::Comment(1)
+          },
+        },
+        '*' | '_' if run <= 3 && !(c == '_' && at > 0 && self.chars[at - 1].is_alphanumeric()) => {
+          let marks = c.to_string().repeat(run);
+          let close = self
+            .find(at + run, &marks)
+            .filter(|close| *close > at + run && !self.chars[close - 1].is_whitespace());
+          match close {
+            Some(close) => {
+              self.push(at, close + run, Class::Keyword);
+              close + run
+            },
+            None => at + run,
+          }
+        },
+        '[' => {
+          let link = self.find(at + 1, "]").and_then(|close| {
+            match self.chars.get(close + 1) {
+              Some('(') => self.find(close + 2, ")").map(|end| (close, end)),
+              _ => None,
+            }
+          });
+          match link {
+            Some((close, end)) => {
+              self.push(at, close + 1, Class::Type);
+              self.push(close + 1, end + 1, Class::String);
+              end + 1
+            },
+            None => at + 1,
+          }
+        },
+        _ => at + 1,
+      };
+    }
+    State::Normal
+  }
+}
@@ -62,7 +62,7 @@ pub fn get_kind(c: char) -> TokenKind {
           _    => TokenKind::Whitespace,
         }
       }
-      else if c.is_alphabetic() { TokenKind::Word }
+      else if c.is_alphabetic() || c == '_' { TokenKind::Word }
       else if c.is_ascii_digit() { TokenKind::Number }
       else { TokenKind::Other }
     },
@@ -78,7 +78,6 @@ pub fn get_kind(c: char) -> TokenKind {
 //------------------------------------------------------
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum TokenKind {
-  Punctuation,
   Number,
   Word,
   NewLine,
@@ -0,0 +1,282 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Tree
+//------------------------------------------------------
+//------------------------------------------------------
+use std::ops::Range;
+use ropey::Rope;
+use tree_sitter::{InputEdit, Node, Parser, Point, Query, QueryCursor, StreamingIterator, Tree};
+use crate::buffer::syntax::{Class, Language, Span};
+use crate::motion::Object;
+
+// A tree-sitter parse of a buffer, edited along with it and parsed again
+// from what the edits left untouched
+pub struct SyntaxTree {
+  language: Language,
+  parser: Parser,
+  highlights: Query,
+  tree: Option<Tree>,
+}
+
+impl SyntaxTree {
+  // None for languages without a grammar
+  pub fn new(language: Language, content: &Rope) -> Option<Self> {
+    let (grammar, highlights) = grammar(language)?;
+    let mut parser = Parser::new();
+    parser.set_language(&grammar).ok()?;
+    let highlights = Query::new(&grammar, highlights).ok()?;
+    let mut tree = SyntaxTree { language, parser, highlights, tree: None };
+    tree.parse(content);
+    Some(tree)
+  }
+
+  // Chars from..to of `content` are about to become `text`
+  pub fn edit(&mut self, content: &Rope, from: usize, to: usize, text: &str) {
+    let Some(tree) = &mut self.tree else {
+      return
+    };
+    let start_byte = content.char_to_byte(from);
+    let start_position = point(content, from);
+    let new_end_position = match text.rfind('\n') {
+      Some(i) => Point::new(start_position.row + text.matches('\n').count(), text.len() - i - 1),
+      None => Point::new(start_position.row, start_position.column + text.len()),
+    };
+    tree.edit(&InputEdit {
+      start_byte,
+      old_end_byte: content.char_to_byte(to),
+      new_end_byte: start_byte + text.len(),
+      start_position,
+      old_end_position: point(content, to),
+      new_end_position,
+    });
+  }
+
+  pub fn parse(&mut self, content: &Rope) {
+    let mut read = |byte: usize, _: Point| -> &[u8] {
+      if byte >= content.len_bytes() {
+        return &[]
+      }
+      let (chunk, start, _, _) = content.chunk_at_byte(byte);
+      &chunk.as_bytes()[byte - start..]
+    };
+    self.tree = self.parser.parse_with_options(&mut read, self.tree.as_ref(), None);
+  }
+
+  // Parses from scratch, for content swapped as a whole
+  pub fn reset(&mut self, content: &Rope) {
+    self.tree = None;
+    self.parse(content);
+  }
+
+  // Spans of row n from the grammar's highlight query. Where captures
+  // overlap the first one wins, like in tree-sitter-highlight.
+  pub fn spans(&self, content: &Rope, n: usize) -> Vec<Span> {
+    let Some(tree) = &self.tree else {
+      return Vec::new()
+    };
+    if n >= content.len_lines() {
+      return Vec::new()
+    }
+    let (start, end) = (content.line_to_byte(n), content.line_to_byte(n + 1));
+    let first = content.line_to_char(n);
+    let len = content.line(n).chars().take_while(|c| *c != '\n').count();
+    let mut classes: Vec<Option<Class>> = vec![None; len];
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(start..end);
+    let text = |node: Node| std::iter::once(content.byte_slice(node.byte_range()).to_string());
+    let mut captures = cursor.captures(&self.highlights, tree.root_node(), text);
+    while let Some((m, i)) = captures.next() {
+      let capture = m.captures[*i];
+      let class = class(self.highlights.capture_names()[capture.index as usize]);
+      let range = capture.node.byte_range();
+      let from = (content.byte_to_char(range.start.max(start)) - first).min(len);
+      let to = (content.byte_to_char(range.end.min(end)) - first).min(len);
+      for slot in classes[from..to].iter_mut().filter(|slot| slot.is_none()) {
+        *slot = Some(class);
+      }
+    }
+    let mut spans: Vec<Span> = Vec::new();
+    for (col, class) in classes.into_iter().enumerate() {
+      let class = class.unwrap_or(Class::Text);
+      match spans.last_mut() {
+        Some(last) if last.class == class => last.to = col + 1,
+        _ => spans.push(Span { from: col, to: col + 1, class }),
+      }
+    }
+    spans
+  }
+
+  // Chars from..to of the `n`th function, class, argument or block
+  // around `cursor`
+  pub fn object(&self, content: &Rope, object: Object, inner: bool, cursor: usize, n: usize) -> Option<(usize, usize)> {
+    let kinds = kinds(self.language, object);
+    let byte = content.char_to_byte(cursor);
+    let mut node = self.tree.as_ref()?.root_node().descendant_for_byte_range(byte, byte)?;
+    let mut left = n;
+    let found = loop {
+      let matched = match object {
+        Object::Argument => node.is_named()
+          && node.kind() != "comment"
+          && node.parent().is_some_and(|parent| kinds.contains(&parent.kind())),
+        _ => kinds.contains(&node.kind()),
+      };
+      if matched {
+        left -= 1;
+        if left == 0 {
+          break node
+        }
+      }
+      node = node.parent()?;
+    };
+    let range = match (object, inner) {
+      (Object::Argument, true) => found.byte_range(),
+      // With the separator after it, or before it for the last one
+      (Object::Argument, false) => match (found.next_named_sibling(), found.prev_named_sibling()) {
+        (Some(next), _) => found.start_byte()..next.start_byte(),
+        (None, Some(prev)) => prev.end_byte()..found.end_byte(),
+        _ => found.byte_range(),
+      },
+      (Object::Function | Object::Class, true) => match found.child_by_field_name("body") {
+        Some(body) => inside(body),
+        None => found.byte_range(),
+      },
+      (_, true) => inside(found),
+      (_, false) => found.byte_range(),
+    };
+    Some((content.byte_to_char(range.start), content.byte_to_char(range.end)))
+  }
+
+  // % from the first or last char of a node to its other end. The
+  // smallest node made of others that starts or ends at `cursor` is
+  // used, so single tokens are skipped.
+  pub fn partner(&self, content: &Rope, cursor: usize) -> Option<usize> {
+    let byte = content.char_to_byte(cursor);
+    let mut node = self.tree.as_ref()?.root_node().descendant_for_byte_range(byte, byte)?;
+    loop {
+      let parent = node.parent()?;
+      let (first, end) = (content.byte_to_char(node.start_byte()), content.byte_to_char(node.end_byte()));
+      if node.child_count() > 0 && end > first + 1 {
+        if cursor == first {
+          return Some(end - 1)
+        }
+        if cursor == end - 1 {
+          return Some(first)
+        }
+      }
+      node = parent;
+    }
+  }
+
+  // Rows of the nodes spanning several rows around row n, innermost
+  // first
+  pub fn folds(&self, content: &Rope, n: usize) -> Vec<(usize, usize)> {
+    let Some(tree) = &self.tree else {
+      return Vec::new()
+    };
+    let indent = content.line(n).chars().take_while(|c| *c == ' ' || *c == '\t').count();
+    let byte = content.char_to_byte(content.line_to_char(n) + indent);
+    let root = tree.root_node();
+    let mut folds: Vec<(usize, usize)> = Vec::new();
+    let mut node = root.descendant_for_byte_range(byte, byte);
+    while let Some(found) = node.filter(|found| found.id() != root.id()) {
+      if let Some(rows) = rows(found).filter(|rows| folds.last() != Some(rows)) {
+        folds.push(rows);
+      }
+      node = found.parent();
+    }
+    folds
+  }
+
+  // Rows of the outermost nodes spanning several rows, for zM
+  pub fn top_folds(&self) -> Vec<(usize, usize)> {
+    let Some(tree) = &self.tree else {
+      return Vec::new()
+    };
+    let root = tree.root_node();
+    let mut walk = root.walk();
+    let folds = root.named_children(&mut walk).filter_map(rows).collect();
+    folds
+  }
+}
+
+fn grammar(language: Language) -> Option<(tree_sitter::Language, &'static str)> {
+  match language {
+    Language::Rust => Some((tree_sitter_rust::LANGUAGE.into(), tree_sitter_rust::HIGHLIGHTS_QUERY)),
+    Language::C => Some((tree_sitter_c::LANGUAGE.into(), tree_sitter_c::HIGHLIGHT_QUERY)),
+    Language::Python => Some((tree_sitter_python::LANGUAGE.into(), tree_sitter_python::HIGHLIGHTS_QUERY)),
+    Language::Json => Some((tree_sitter_json::LANGUAGE.into(), tree_sitter_json::HIGHLIGHTS_QUERY)),
+    _ => None,
+  }
+}
+
+// Node kinds of each text object. For arguments these are the lists
+// holding them.
+fn kinds(language: Language, object: Object) -> &'static [&'static str] {
+  match (language, object) {
+    (Language::Rust, Object::Function) => &["function_item", "closure_expression"],
+    (Language::Rust, Object::Class) => &["struct_item", "enum_item", "union_item", "trait_item", "impl_item"],
+    (Language::Rust, Object::Argument) => &[
+      "arguments", "parameters", "type_arguments", "type_parameters", "tuple_expression", "tuple_pattern",
+    ],
+    (Language::Rust, Object::Block) => &[
+      "block", "declaration_list", "field_declaration_list", "enum_variant_list", "match_block",
+    ],
+    (Language::C, Object::Function) => &["function_definition"],
+    (Language::C, Object::Class) => &["struct_specifier", "union_specifier", "enum_specifier"],
+    (Language::C, Object::Argument) => &["argument_list", "parameter_list"],
+    (Language::C, Object::Block) => &["compound_statement", "field_declaration_list", "enumerator_list", "initializer_list"],
+    (Language::Python, Object::Function) => &["function_definition", "lambda"],
+    (Language::Python, Object::Class) => &["class_definition"],
+    (Language::Python, Object::Argument) => &["argument_list", "parameters", "lambda_parameters"],
+    (Language::Python, Object::Block) => &["block"],
+    (Language::Json, Object::Argument | Object::Block) => &["object", "array"],
+    _ => &[],
+  }
+}
+
+// Highlight capture names to classes, the ones left out stay plain
+fn class(name: &str) -> Class {
+  match name {
+    "function.macro" | "attribute" | "label" | "variable.builtin" => Class::Keyword,
+    "string.special.key" => Class::Type,
+    _ => match name.split_once('.').map_or(name, |(head, _)| head) {
+      "keyword" => Class::Keyword,
+      "string" | "escape" => Class::String,
+      "comment" => Class::Comment,
+      "number" | "constant" => Class::Number,
+      "type" | "constructor" => Class::Type,
+      "operator" => Class::Operator,
+      _ => Class::Text,
+    },
+  }
+}
+
+// What is between a node's first and last named children, or right
+// after its opening token when it has none
+fn inside(node: Node) -> Range<usize> {
+  let mut walk = node.walk();
+  let named: Vec<Node> = node.named_children(&mut walk).collect();
+  match (named.first(), named.last()) {
+    (Some(first), Some(last)) => first.start_byte()..last.end_byte(),
+    _ => {
+      let at = node.child(0).map_or(node.start_byte(), |open| open.end_byte());
+      at..at
+    },
+  }
+}
+
+// First and last row of a node spanning several rows. A node ending
+// with its line break ends on the row before.
+fn rows(node: Node) -> Option<(usize, usize)> {
+  let (start, end) = (node.start_position(), node.end_position());
+  let last = match end.column {
+    0 => end.row.saturating_sub(1),
+    _ => end.row,
+  };
+  (last > start.row).then_some((start.row, last))
+}
+
+fn point(content: &Rope, char: usize) -> Point {
+  let row = content.char_to_line(char);
+  Point::new(row, content.char_to_byte(char) - content.line_to_byte(row))
+}
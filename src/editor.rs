@@ -0,0 +1,1464 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Editor
+//------------------------------------------------------
+//------------------------------------------------------
+use std::collections::VecDeque;
+use std::path::Path;
+
+use sdl2::clipboard::ClipboardUtil;
+
+use crate::handler::{EventHandler, HandleResult, Mode, ESC, ENTER};
+use crate::layout::{Area, Axis, Layout, Scroll};
+use crate::options::Options;
+use crate::register::Registers;
+use crate::search::{self, Pattern, Search};
+use crate::substitute::{Confirm, Substitute};
+use crate::buffer::buffer::{Buffer, Yanked};
+use crate::buffer::list::BufferList;
+use crate::buffer::syntax::Language;
+use crate::motion::*;
+
+// Keys handled for one key typed before a playing macro is stopped, ends
+// macros that call themselves with nothing failing
+const MACRO_LIMIT: usize = 1_000_000;
+
+// First and last row an Ex command works on
+type Range = (usize, usize);
+
+// The last change, repeated with `.`. `insert` is set when the command
+// started Insert mode, `typed` holds what was typed there. An operator
+// on a Visual selection keeps the selection's extent.
+#[derive(Debug, Clone)]
+struct Change {
+  result: HandleResult,
+  insert: bool,
+  typed: String,
+  extent: Option<(usize, usize)>,
+}
+
+// Everything but the window: keys go in with feed and the state is read
+// back for drawing. Needs no SDL event pump, so key sequences can be run
+// without a window.
+pub struct Editor {
+  pub handler: EventHandler,
+  pub buffers: BufferList,
+  pub layout: Layout,
+  pub options: Options,
+  pub message: String,
+  pub search: Search,
+  // Part of the window the panes share and the size of one character
+  // cell, kept up to date by the window
+  pub area: Area,
+  pub cell: (u32, u32),
+  registers: Registers,
+  // "+ and "* stay inside the editor without one
+  clipboard: Option<ClipboardUtil>,
+  last_change: Option<Change>,
+  // Change waiting for its Insert mode to end
+  insert_change: Option<Change>,
+  // Insert mode has changed the buffer since the last undo step
+  inserted: bool,
+  // Keys waiting to be handled, played macros go here
+  queue: VecDeque<char>,
+  // Cursor from before the pattern being typed moved it
+  search_origin: Option<usize>,
+  // The pattern being typed, compiled
+  preview: Option<Pattern>,
+  // :s with the c flag waiting for an answer
+  confirm: Option<Confirm>,
+}
+
+impl Editor {
+  pub fn new(buffer: Buffer, area: Area, cell: (u32, u32), clipboard: Option<ClipboardUtil>) -> Self {
+    Editor {
+      handler: EventHandler::new(),
+      buffers: BufferList::new(buffer),
+      layout: Layout::new(1),
+      options: Options::default(),
+      message: String::new(),
+      search: Search::new(),
+      area,
+      cell,
+      registers: Registers::new(),
+      clipboard,
+      last_change: None,
+      insert_change: None,
+      inserted: false,
+      queue: VecDeque::new(),
+      search_origin: None,
+      preview: None,
+      confirm: None,
+    }
+  }
+
+  // A key typed by the user, true asks the editor to quit
+  pub fn feed(&mut self, key: char) -> bool {
+    self.handler.record(key);
+    self.queue.push_back(key);
+    self.drain()
+  }
+
+  fn drain(&mut self) -> bool {
+    let mut handled = 0;
+    while let Some(key) = self.queue.pop_front() {
+      handled += 1;
+      if handled > MACRO_LIMIT {
+        self.queue.clear();
+        self.message = "Macro stopped after too many keys".to_string();
+        break
+      }
+      let quit = self.key(key);
+      self.move_pane_cursors();
+      if quit {
+        return true
+      }
+    }
+    false
+  }
+
+  fn key(&mut self, key: char) -> bool {
+    use HandleResult::*;
+    if self.confirm.is_some() {
+      self.confirm_key(key);
+      return false
+    }
+    let mut m_buff = String::new();
+    let before = self.handler.mode();
+    let result = self.handler.handle(key, &mut m_buff);
+    if result != None {
+      self.message.clear();
+    }
+    let extent = match before {
+      Mode::Visual(visual) => self.buffers.current().selection_extent(visual),
+      _ => Option::None,
+    };
+    match result {
+      None => {},
+      Quit => return self.quit(),
+      Motion(m) => {
+        let cursor = self.buffers.current().cursor;
+        let yanked = self.buffers.current_mut().apply_motion(m, self.handler.mode());
+        match yanked {
+          // Backspacing in Insert mode does not fill the registers
+          Some(_) if m == CUTBACK => self.registers.backspace(),
+          Some(yanked) => if let Err(e) = self.store(m.register, yanked, m.verb != Action::Yank) {
+            self.message = e
+          },
+          _ => {},
+        }
+        if m.is_disruptive() {
+          match self.handler.mode() {
+            Mode::Insert => self.inserted = true,
+            _ => self.buffers.current_mut().commit(),
+          }
+        }
+        // Like in vim a j, k or seek that cannot move ends the macro
+        // playing, which is what stops recursive ones
+        let failed = matches!(m.target, Target::Dir(Dir::U | Dir::D) | Target::Dest(Dest::Seek(..)));
+        if failed && m.verb == Action::Move && self.buffers.current().cursor == cursor {
+          self.queue.clear();
+        }
+      },
+      Insert => {
+        self.buffers.current_mut().insert_at_cursor(&m_buff);
+        self.registers.typed(&m_buff);
+        self.inserted = true;
+      }
+      NewlineSplit => {
+        self.buffers.current_mut().insert_at_cursor("\n");
+        self.registers.typed("\n");
+        self.inserted = true;
+      },
+      NewlineNoSplit => {}, //self.buffer.insert_newline(Dir::D, false),
+      NewlineUp => {}, // self.buffer.insert_newline(Dir::U, false),
+      SetEditMode => {
+        let buffer = self.buffers.current_mut();
+        if !buffer.finish_block_insert() {
+          buffer.apply_motion(MOVE_L, Mode::Edit);
+        }
+        let typed = self.registers.finish_insert();
+        if let Some(change) = self.insert_change.take() {
+          self.last_change = Some(Change { typed, ..change });
+        }
+        if self.inserted {
+          buffer.commit();
+          self.inserted = false;
+        }
+      },
+      Command => {
+        self.registers.set_last_command(&m_buff);
+        match self.handle_cmd_input(&m_buff) {
+          Ok(true) => return true,
+          Ok(false) => {},
+          Err(e) => self.message = e,
+        }
+      }
+      Undo => self.buffers.current_mut().undo(),
+      Redo => self.buffers.current_mut().redo(),
+      Earlier => self.buffers.current_mut().earlier(),
+      Later => self.buffers.current_mut().later(),
+      Window(key) => if let Err(e) = self.handle_window_cmd(key) {
+        self.message = e
+      },
+      Scroll(scroll) => self.scroll(scroll),
+      DisplayLine(n) => self.move_display_line(n),
+      PrintHistory => self.message = format!("HISTORY:\n{}", self.buffers.current().history()),
+      Visual => {},
+      OtherEnd => self.buffers.current_mut().other_end(),
+      Shift(n) => {
+        let buffer = self.buffers.current_mut();
+        buffer.shift_selection(n, self.options.shiftwidth);
+        buffer.commit();
+      },
+      BlockInsert(append) => self.buffers.current_mut().block_insert(append),
+      Put(register, count, before) => self.put(register, count, before),
+      Repeat(count) => if let Some(change) = self.last_change.clone() {
+        self.repeat(&change, count)
+      },
+      // The paste is an undo step of its own
+      Paste => if let Some(yanked) = self.register(Some('+')) {
+        let buffer = self.buffers.current_mut();
+        if self.inserted {
+          buffer.commit();
+          self.inserted = false;
+        }
+        buffer.insert_at_cursor(&yanked.text);
+        buffer.commit();
+        self.registers.typed(&yanked.text);
+      },
+      Recorded => if let Some((register, keys)) = self.handler.take_recording() {
+        if let Err(e) = self.registers.record(register, &keys) {
+          self.message = e
+        }
+      },
+      Play(register, count) => self.play(register, count),
+      Search(forward, count) => {
+        if let Some(origin) = self.search_origin.take() {
+          self.buffers.current_mut().set_cursor(origin);
+        }
+        // An empty pattern searches for the last one again
+        match (m_buff.is_empty(), self.search.last.clone()) {
+          (true, Some((pattern, _))) => self.find(&pattern, forward, count),
+          (true, _) => self.message = "No previous regular expression".to_string(),
+          (false, _) => self.find(&m_buff, forward, count),
+        }
+      },
+      SearchNext(reverse, count) => match self.search.last.clone() {
+        Some((pattern, forward)) => self.find(&pattern, forward != reverse, count),
+        _ => self.message = "No previous regular expression".to_string(),
+      },
+      // The search starts from the start of the word
+      SearchWord(forward, count) => match self.buffers.current().word_under_cursor() {
+        Some((start, word)) => {
+          self.buffers.current_mut().set_cursor(start);
+          self.find(&search::whole_word(&word, self.options.regex), forward, count)
+        },
+        _ => self.message = "No string under cursor".to_string(),
+      },
+      History(step) => if let Some(pattern) = self.search.browse(step) {
+        self.handler.set_command(pattern)
+      },
+      Fold(key) => if let Err(e) = self.buffers.current_mut().fold(key) {
+        self.message = e
+      },
+    }
+    self.preview_search();
+    // Moving into a closed fold opens it
+    self.buffers.current_mut().reveal_cursor();
+    self.record(result, before, extent);
+    // The selection anchor lives as long as Visual mode
+    match self.handler.mode() {
+      Mode::Visual(_) => self.buffers.current_mut().start_visual(),
+      _ => self.buffers.current_mut().end_visual(),
+    }
+    self.follow_cursor();
+    false
+  }
+
+  // Moves to the `count`th match of `pattern` and makes it the one n and
+  // N look for
+  fn find(&mut self, pattern: &str, forward: bool, count: usize) {
+    let compiled = match Pattern::new(pattern, self.options.regex, false) {
+      Ok(compiled) => compiled,
+      Err(e) => return self.message = e,
+    };
+    let buffer = self.buffers.current_mut();
+    let mut cursor = buffer.cursor;
+    let mut wrapped = false;
+    let mut found = true;
+    for _ in 0..count {
+      match buffer.search(&compiled, cursor, forward) {
+        Some((at, wrap)) => {
+          cursor = at;
+          wrapped |= wrap;
+        },
+        None => found = false,
+      }
+    }
+    self.search.push(pattern, forward, compiled);
+    if !found {
+      return self.message = format!("Pattern not found: {}", pattern)
+    }
+    buffer.set_cursor(cursor);
+    if wrapped {
+      self.message = match forward {
+        true  => "search hit BOTTOM, continuing at TOP",
+        false => "search hit TOP, continuing at BOTTOM",
+      }.to_string();
+    }
+  }
+
+  // While a pattern is typed the cursor shows the match it leads to, and
+  // goes back where it was if the search is cancelled
+  fn preview_search(&mut self) {
+    let buffer = self.buffers.current_mut();
+    match (self.handler.searching(), self.search_origin) {
+      (Some(forward), _) => {
+        if self.search_origin.is_none() {
+          self.search.stop_browsing();
+        }
+        let origin = *self.search_origin.get_or_insert(buffer.cursor);
+        self.preview = Pattern::new(self.handler.command(), self.options.regex, false).ok();
+        let found = self.preview
+          .as_ref()
+          .and_then(|pattern| buffer.search(pattern, origin, forward))
+          .map(|(found, _)| found);
+        buffer.set_cursor(found.unwrap_or(origin));
+      },
+      (None, Some(origin)) => {
+        buffer.set_cursor(origin);
+        self.search_origin = None;
+        self.preview = None;
+      },
+      (None, None) => {},
+    }
+  }
+
+  // Pattern whose matches are highlighted, the one being typed or the
+  // last one searched for
+  pub fn highlight(&self) -> Option<&Pattern> {
+    match self.handler.searching() {
+      Some(_) => self.preview.as_ref(),
+      None if self.search.highlight => self.search.pattern.as_ref(),
+      None => None,
+    }
+  }
+
+  // Match :s with the c flag is asking about: row and columns from..to
+  pub fn current_match(&self) -> Option<(usize, usize, usize)> {
+    let (row, m) = self.confirm.as_ref()?.current.clone()?;
+    let line = self.buffers.current().nth(row);
+    Some((row, line[..m.start].chars().count(), line[..m.end].chars().count()))
+  }
+
+  // :s, replaces in every row of the range at once, or one match at a
+  // time with the c flag
+  fn substitute(&mut self, range: Option<Range>, args: &str) -> Result<(), String> {
+    let last_search = self.search.last.as_ref().map(|(pattern, _)| pattern.clone());
+    let sub = Substitute::parse(args, last_search.as_deref(), self.options.regex)?;
+    self.search.push(&sub.pattern, true, sub.compiled().clone());
+    let buffer = self.buffers.current_mut();
+    let row = buffer.row();
+    let (first, last) = match (range.unwrap_or((row, row)), sub.count) {
+      ((_, last), Some(count)) => (last, last + count - 1),
+      (range, None) => range,
+    };
+    let last = last.min(buffer.height() - 1);
+    if sub.confirm {
+      self.confirm = Some(Confirm::new(sub, first, last));
+      self.confirm_next();
+      return Ok(())
+    }
+    let mut rows = Vec::new();
+    let (mut done, mut breaks, mut last_changed) = (0, 0, row);
+    for row in first..=last {
+      let (text, n) = sub.apply(&buffer.nth(row));
+      if n > 0 {
+        done += n;
+        last_changed = row + breaks;
+        breaks += text.matches('\n').count();
+        rows.push((row, text));
+      }
+    }
+    match (done, sub.quiet) {
+      (0, true) => return Ok(()),
+      (0, false) => return Err(format!("Pattern not found: {}", sub.pattern)),
+      _ => {},
+    }
+    if !sub.count_only {
+      buffer.replace_rows(&rows);
+      buffer.commit();
+      buffer.goto_row(last_changed);
+    }
+    self.message = summary(done, rows.len(), sub.count_only);
+    Ok(())
+  }
+
+  // Shows the next match to confirm, or ends the substitution
+  fn confirm_next(&mut self) {
+    let Some(confirm) = &mut self.confirm else {
+      return
+    };
+    let buffer = self.buffers.current_mut();
+    if confirm.next(buffer) {
+      if let Some((row, m)) = &confirm.current {
+        let start = buffer.content.line_to_char(*row);
+        buffer.set_cursor(start + buffer.nth(*row)[..m.start].chars().count());
+      }
+      self.message = format!("replace with {} (y/n/a/q/l)", confirm.sub.replacement_text());
+      return
+    }
+    let confirm = self.confirm.take().unwrap();
+    buffer.commit();
+    self.message = match confirm.done {
+      0 if confirm.sub.quiet => String::new(),
+      0 => format!("Pattern not found: {}", confirm.sub.pattern),
+      done => summary(done, confirm.lines, false),
+    };
+  }
+
+  // y replaces, l replaces and stops, n skips, a replaces the rest and
+  // q or Esc stops
+  fn confirm_key(&mut self, key: char) {
+    let (Some(confirm), buffer) = (&mut self.confirm, self.buffers.current_mut()) else {
+      return
+    };
+    match key {
+      'y' => confirm.replace(buffer),
+      'l' => {
+        confirm.replace(buffer);
+        confirm.stop();
+      },
+      'n' => confirm.skip(buffer),
+      'a' => {
+        confirm.replace(buffer);
+        while confirm.next(buffer) {
+          confirm.replace(buffer);
+        }
+      },
+      'q' | ESC => confirm.stop(),
+      _ => return,
+    }
+    self.confirm_next();
+  }
+
+  // @, queues the keys of a register in front of the waiting ones so a
+  // macro played from a macro runs where it is called. Line breaks of
+  // linewise registers are played as Enter.
+  fn play(&mut self, register: char, count: usize) {
+    let keys = match register {
+      ':' => self.registers.get(Some(':'), "").map(|cmd| format!(":{}{}", cmd.text, ENTER)),
+      _ => self.register(Some(register)).map(|yanked| yanked.text.replace('\n', &ENTER.to_string())),
+    };
+    match keys {
+      Some(keys) => for key in keys.repeat(count).chars().rev() {
+        self.queue.push_front(key);
+      },
+      _ => self.message = format!("Nothing in register {}", register),
+    }
+  }
+
+  // :normal, runs `keys` in Edit mode on each row of the range with the
+  // cursor at the start of the row
+  fn normal(&mut self, range: Option<Range>, keys: &str) -> bool {
+    let row = self.buffers.current().row();
+    let (first, last) = range.unwrap_or((row, row));
+    let outer = std::mem::take(&mut self.queue);
+    let mut quit = false;
+    for row in first..=last {
+      let buffer = self.buffers.current_mut();
+      if quit || row >= buffer.height() {
+        break
+      }
+      buffer.set_cursor(buffer.content.line_to_char(row));
+      self.queue.extend(keys.chars());
+      quit = self.drain();
+      // A command left unfinished is dropped like with Esc
+      if self.handler.mode() != Mode::Edit {
+        quit |= self.key(ESC);
+      }
+      self.handler.reset();
+    }
+    self.queue = outer;
+    quit
+  }
+
+  pub fn focused_area(&self) -> Area {
+    let focus = self.layout.focused().id;
+    self.layout
+      .arrange(self.area)
+      .into_iter()
+      .find(|(id, _)| *id == focus)
+      .map_or(self.area, |(_, area)| area)
+  }
+
+  // Rows and columns of text fitting in the focused pane
+  fn viewport(&self) -> (usize, usize) {
+    let area = self.focused_area();
+    (
+      (area.h / self.cell.1) as usize,
+      (area.w / self.cell.0).max(1) as usize,
+    )
+  }
+
+  fn scroll(&mut self, scroll: Scroll) {
+    let (rows, _) = self.viewport();
+    let buffer = self.buffers.current_mut();
+    let last = buffer.height() - 1;
+    let row = self.layout
+      .focused_mut()
+      .scroll(scroll, buffer.row(), rows, last, self.options.scrolloff);
+    buffer.set_row(row);
+  }
+
+  pub fn follow_cursor(&mut self) {
+    let (rows, cols) = self.viewport();
+    let so = self.options.scrolloff;
+    let buffer = self.buffers.current();
+    let pane = self.layout.focused_mut();
+    let last = buffer.height() - 1;
+    match self.options.wrap {
+      true  => {
+        pane.left = 0;
+        let sub_row = match buffer.fold_at(buffer.row()) {
+          Some(_) => 0,
+          None => buffer.col() / cols,
+        };
+        pane.follow_wrapped(buffer.row(), sub_row, rows, last, so, |i| buffer.display_height(i, cols));
+      },
+      false => {
+        pane.follow_wrapped(buffer.row(), 0, rows, last, so, |i| buffer.display_height(i, usize::MAX));
+        pane.follow_col(buffer.col(), cols);
+      },
+    }
+  }
+
+  fn move_display_line(&mut self, n: isize) {
+    let (_, cols) = self.viewport();
+    let wrap = self.options.wrap;
+    let buffer = self.buffers.current_mut();
+    match wrap {
+      true  => buffer.move_display_line(n, cols),
+      false => buffer.set_row((buffer.row() as isize + n).max(0) as usize),
+    }
+  }
+
+  // The focused pane's cursor lives in its buffer while it has focus
+  fn store_cursor(&mut self) {
+    let cursor = self.buffers.current().cursor;
+    self.layout.focused_mut().cursor = cursor;
+  }
+
+  // Other panes on the current buffer keep their cursors on the same
+  // text over its edits
+  fn move_pane_cursors(&mut self) {
+    let shift = self.buffers.current_mut().take_moves();
+    let (focused, buffer) = (self.layout.focused().id, self.buffers.current_id());
+    for id in self.layout.ids() {
+      let pane = self.layout.get_mut(id).unwrap();
+      if pane.id != focused && pane.buffer == buffer {
+        pane.cursor = shift(pane.cursor);
+      }
+    }
+  }
+
+  fn focus_pane(&mut self, id: usize) {
+    self.store_cursor();
+    self.layout.focus(id);
+    let pane = *self.layout.focused();
+    if self.buffers.switch(pane.buffer).is_ok() {
+      self.buffers.current_mut().set_cursor(pane.cursor);
+    }
+  }
+
+  // Shows the buffer list's current buffer in the focused pane, and
+  // moves panes off buffers that no longer exist.
+  fn sync_panes(&mut self) {
+    let current = self.buffers.current_id();
+    self.layout.focused_mut().buffer = current;
+    for id in self.layout.ids() {
+      let pane = self.layout.get_mut(id).unwrap();
+      if self.buffers.get(pane.buffer).is_none() {
+        pane.buffer = current;
+        pane.cursor = 0;
+        pane.top = 0;
+      }
+    }
+  }
+
+  fn handle_window_cmd(&mut self, key: char) -> Result<(), String> {
+    let dir = match key {
+      'h' => Dir::L,
+      'j' => Dir::D,
+      'k' => Dir::U,
+      'l' => Dir::R,
+      'w' => {
+        let ids = self.layout.ids();
+        let pos = ids.iter().position(|id| *id == self.layout.focused().id).unwrap_or(0);
+        self.focus_pane(ids[(pos + 1) % ids.len()]);
+        return Ok(())
+      },
+      's' => return self.split(Axis::Horizontal, None),
+      'v' => return self.split(Axis::Vertical, None),
+      'c' | 'q' => return self.close_pane(),
+      'o' => {
+        self.layout.only();
+        return Ok(())
+      },
+      _ => return Ok(()),
+    };
+    if let Some(id) = self.layout.neighbour(self.area, dir) {
+      self.focus_pane(id)
+    }
+    Ok(())
+  }
+
+  fn split(&mut self, axis: Axis, path: Option<&Path>) -> Result<(), String> {
+    self.store_cursor();
+    self.layout.split(axis);
+    if let Some(path) = path {
+      self.buffers.open(path)?;
+      self.sync_panes();
+    }
+    Ok(())
+  }
+
+  // :set filetype, which belongs to the current buffer
+  fn set_filetype(&mut self, arg: &str) -> Result<String, String> {
+    let buffer = self.buffers.current_mut();
+    match arg.split_once('=') {
+      Some((_, name)) => {
+        let language = Language::from_name(name).ok_or(format!("Invalid argument: {}", arg))?;
+        buffer.set_language(language);
+        Ok(String::new())
+      },
+      None => Ok(format!("filetype={}", buffer.language().name())),
+    }
+  }
+
+  fn close_pane(&mut self) -> Result<(), String> {
+    self.layout.close()?;
+    let pane = *self.layout.focused();
+    if self.buffers.switch(pane.buffer).is_ok() {
+      self.buffers.current_mut().set_cursor(pane.cursor);
+    }
+    Ok(())
+  }
+
+  // Ok(true) asks the editor to quit
+  fn handle_cmd_input(&mut self, cmd: &str) -> Result<bool, String> {
+    let (range, cmd) = parse_range(cmd, self.buffers.current())?;
+    if cmd.trim().is_empty() {
+      if let Some((_, last)) = range {
+        self.buffers.current_mut().goto_row(last);
+      }
+      return Ok(false)
+    }
+    // :s takes its pattern right after the name
+    let args = cmd.trim().strip_prefix("substitute").or(cmd.trim().strip_prefix('s'));
+    if let Some(args) = args.filter(|args| args.starts_with(|c: char| !c.is_alphanumeric())) {
+      self.substitute(range, args.trim_start())?;
+      return Ok(false)
+    }
+    let (name, arg) = match cmd.trim().split_once(' ') {
+      Some((name, arg)) => (name, Some(arg.trim())),
+      None => (cmd.trim(), None),
+    };
+    let path = arg.map(Path::new);
+    match name {
+      "w" => self.message = self.buffers.current_mut().save(path)?,
+      "q" | "close" | "clo" if self.layout.count() > 1 => self.close_pane()?,
+      "q" => {
+        self.check_saved()?;
+        return Ok(true)
+      },
+      "close" | "clo" => self.layout.close()?,
+      "only" | "on" => self.layout.only(),
+      "set" | "se" => {
+        let mut shown = Vec::new();
+        for arg in arg.unwrap_or("").split_whitespace() {
+          shown.push(match arg.split_once('=').map_or(arg.trim_end_matches('?'), |(name, _)| name) {
+            "filetype" | "ft" => self.set_filetype(arg)?,
+            _ => self.options.set(arg)?,
+          });
+        }
+        self.message = shown.join(" ").trim().to_string();
+      },
+      "split" | "sp" => self.split(Axis::Horizontal, path)?,
+      "vsplit" | "vs" => self.split(Axis::Vertical, path)?,
+      "q!" => return Ok(true),
+      "wq" => {
+        self.buffers.current_mut().save(path)?;
+        self.check_saved()?;
+        return Ok(true)
+      },
+      "x" => {
+        if self.buffers.current().is_dirty() {
+          self.buffers.current_mut().save(path)?;
+        }
+        self.check_saved()?;
+        return Ok(true)
+      },
+      "e" => match path {
+        Some(path) => {
+          self.buffers.open(path)?;
+          self.message = self.file_message();
+        },
+        // Without a name the current file is read again
+        None if self.buffers.current().is_dirty() => {
+          return Err("No write since last change (add ! to override)".to_string())
+        },
+        None => self.reload(None)?,
+      },
+      "e!" => self.reload(path)?,
+      "reg" | "registers" | "di" | "display" => {
+        self.message = self.registers.list(arg, &self.file_path());
+      },
+      "noh" | "nohlsearch" => self.search.highlight = false,
+      // The keys keep their spaces
+      "norm" | "normal" | "norm!" | "normal!" => {
+        let keys = cmd.trim_start()[name.len()..].strip_prefix(' ').unwrap_or("");
+        return Ok(self.normal(range, keys))
+      },
+      "ls" | "buffers" => {
+        self.message = self.buffers
+          .iter()
+          .map(|(id, buffer)| format!(
+            "{:>3} {}{} \"{}\"",
+            id,
+            if id == self.buffers.current_id() { "%" } else { " " },
+            if buffer.is_dirty() { "+" } else { " " },
+            buffer.file.name(),
+          ))
+          .collect::<Vec<_>>()
+          .join("\n");
+      },
+      "b" | "buffer" => match arg.map(|arg| arg.parse::<usize>()) {
+        Some(Ok(id)) => self.buffers.switch(id)?,
+        _ => return Err(format!("Invalid buffer number: {}", arg.unwrap_or(""))),
+      },
+      "bn" | "bnext" => self.buffers.next(),
+      "bp" | "bprevious" => self.buffers.prev(),
+      "bd" | "bdelete" | "bd!" | "bdelete!" => {
+        let id = match arg.map(|arg| arg.parse::<usize>()) {
+          Some(Ok(id)) => id,
+          Some(Err(_)) => return Err(format!("Invalid buffer number: {}", arg.unwrap_or(""))),
+          None => self.buffers.current_id(),
+        };
+        self.buffers.remove(id, name.ends_with('!'))?;
+      },
+      _ => return Err(format!("Not an editor command: {}", cmd)),
+    }
+    self.sync_panes();
+    Ok(false)
+  }
+
+  // Ctrl-c or closing the window, refused like :q while a buffer has
+  // unsaved changes
+  pub fn quit(&mut self) -> bool {
+    match self.check_saved() {
+      Ok(()) => true,
+      Err(e) => {
+        self.message = e;
+        false
+      },
+    }
+  }
+
+  fn check_saved(&self) -> Result<(), String> {
+    match self.buffers.first_dirty() {
+      Some((id, buffer)) => Err(format!(
+        "No write since last change for buffer {} \"{}\" (add ! to override)",
+        id,
+        buffer.file.name(),
+      )),
+      None => Ok(()),
+    }
+  }
+
+  fn put(&mut self, register: Option<char>, count: usize, before: bool) {
+    match self.register(register) {
+      Some(yanked) => {
+        let buffer = self.buffers.current_mut();
+        buffer.put(&yanked, before, count);
+        buffer.commit();
+      },
+      _ => self.message = format!("Nothing in register {}", register.unwrap_or('"')),
+    }
+  }
+
+  // Remembers changes made from Edit mode for `.`. Commands starting
+  // Insert mode are kept aside until it ends.
+  fn record(&mut self, result: HandleResult, before: Mode, extent: Option<(usize, usize)>) {
+    let change = Change { result, insert: false, typed: String::new(), extent };
+    match (before, result) {
+      (Mode::Edit, _) => {},
+      (Mode::Visual(_), HandleResult::Motion(m)) if m.is_disruptive() && matches!(m.target, Target::Selection(_)) => {},
+      (Mode::Visual(_), HandleResult::Shift(_)) => {},
+      _ => return,
+    }
+    match (self.handler.mode(), result) {
+      (Mode::Insert, _) => self.insert_change = Some(Change { insert: true, ..change }),
+      (Mode::Edit, HandleResult::Motion(m)) if m.is_disruptive() => self.last_change = Some(change),
+      (Mode::Edit, HandleResult::Put(..) | HandleResult::Shift(_)) => self.last_change = Some(change),
+      _ => {},
+    }
+  }
+
+  // `.`, replays the change and the text typed after it as one undo step
+  fn repeat(&mut self, change: &Change, count: Option<usize>) {
+    let mode = match change.insert {
+      true  => Mode::Insert,
+      false => Mode::Edit,
+    };
+    match change.result {
+      HandleResult::Motion(mut m) => {
+        m.count = count.or(m.count);
+        if let (Target::Selection(visual), Some(extent)) = (m.target, change.extent) {
+          self.buffers.current_mut().select_extent(visual, extent);
+        }
+        let yanked = self.buffers.current_mut().apply_motion(m, mode);
+        if let Some(yanked) = yanked {
+          if let Err(e) = self.store(m.register, yanked, m.verb != Action::Yank) {
+            self.message = e
+          }
+        }
+      },
+      HandleResult::Put(register, n, before) => return self.put(register, count.unwrap_or(n), before),
+      HandleResult::Shift(n) => {
+        let buffer = self.buffers.current_mut();
+        buffer.select_extent(Visual::Line, change.extent.unwrap_or((0, 0)));
+        buffer.shift_selection(n, self.options.shiftwidth);
+      },
+      _ => {},
+    }
+    let buffer = self.buffers.current_mut();
+    if change.insert {
+      buffer.insert_at_cursor(&change.typed);
+      if !buffer.finish_block_insert() {
+        buffer.apply_motion(MOVE_L, Mode::Edit);
+      }
+    }
+    buffer.commit();
+  }
+
+  // Registers "+ and "* go through the system clipboard. Without a
+  // register name the one from the clipboard option is used.
+  fn store(&mut self, register: Option<char>, yanked: Yanked, delete: bool) -> Result<(), String> {
+    let register = register.or(self.options.clipboard);
+    if let (Some('+' | '*'), Some(clipboard)) = (register, &self.clipboard) {
+      clipboard.set_clipboard_text(&yanked.text)?;
+    }
+    self.registers.store(register, yanked, delete)
+  }
+
+  fn register(&self, register: Option<char>) -> Option<Yanked> {
+    let register = register.or(self.options.clipboard);
+    match (register, &self.clipboard) {
+      (Some('+' | '*'), Some(clipboard)) => clipboard
+        .clipboard_text()
+        .ok()
+        .filter(|text| !text.is_empty())
+        .map(|text| self.registers.clipboard(&text)),
+      _ => self.registers.get(register, &self.file_path()),
+    }
+  }
+
+  // Path of the current buffer for "%, empty without one
+  fn file_path(&self) -> String {
+    self.buffers
+      .current()
+      .file
+      .path
+      .as_ref()
+      .map_or(String::new(), |path| path.display().to_string())
+  }
+
+  // Replaces the current buffer with `path`, or with its own file read
+  // again, dropping its changes
+  fn reload(&mut self, path: Option<&Path>) -> Result<(), String> {
+    let buffer = self.buffers.current_mut();
+    let path = match (path, &buffer.file.path) {
+      (Some(path), _) => path.to_path_buf(),
+      (None, Some(own)) => own.clone(),
+      (None, None) => return Err("No file name".to_string()),
+    };
+    *buffer = Buffer::open(&path)?;
+    self.message = self.file_message();
+    Ok(())
+  }
+
+  fn file_message(&self) -> String {
+    let buffer = self.buffers.current();
+    format!("\"{}\" {}L", buffer.file.name(), buffer.height())
+  }
+}
+
+// Splits the line range off the front of an Ex command: `%` or one or
+// two addresses separated by a comma. Returns zero based rows, in order
+// and inside the buffer, and the rest of the command.
+fn parse_range<'a>(cmd: &'a str, buffer: &Buffer) -> Result<(Option<Range>, &'a str), String> {
+  let cmd = cmd.trim_start();
+  let last = buffer.height() - 1;
+  if let Some(rest) = cmd.strip_prefix('%') {
+    return Ok((Some((0, last)), rest))
+  }
+  let Some((first, rest)) = address(cmd, buffer)? else {
+    return Ok((None, cmd))
+  };
+  let (second, rest) = match rest.strip_prefix(',') {
+    Some(rest) => address(rest, buffer)?.ok_or(format!("Invalid range: {}", cmd))?,
+    None => (first, rest),
+  };
+  Ok((Some((first.min(second).min(last), first.max(second).min(last))), rest))
+}
+
+// A line number, `.`, `$` or one of the marks '< and '> of the last
+// selection, followed by any number of +N and -N. A lone offset counts
+// from the cursor's row.
+fn address<'a>(text: &'a str, buffer: &Buffer) -> Result<Option<(usize, &'a str)>, String> {
+  let digits = |text: &str| text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+  let selection = || buffer.last_visual().ok_or("Mark not set".to_string());
+  let n = digits(text);
+  let (mut row, mut rest) = match text.chars().next() {
+    _ if n > 0 => (text[..n].parse::<isize>().map_err(|e| e.to_string())? - 1, &text[n..]),
+    Some('.') => (buffer.row() as isize, &text[1..]),
+    Some('$') => (buffer.height() as isize - 1, &text[1..]),
+    Some('+' | '-') => (buffer.row() as isize, text),
+    _ if text.starts_with("'<") => (selection()?.0 as isize, &text[2..]),
+    _ if text.starts_with("'>") => (selection()?.1 as isize, &text[2..]),
+    _ => return Ok(None),
+  };
+  loop {
+    let sign = match rest.chars().next() {
+      Some('+') => 1,
+      Some('-') => -1,
+      _ => break,
+    };
+    let n = digits(&rest[1..]);
+    let offset = match n {
+      0 => 1,
+      n => rest[1..=n].parse::<isize>().map_err(|e| e.to_string())?,
+    };
+    row += sign * offset;
+    rest = &rest[n + 1..];
+  }
+  Ok(Some((row.max(0) as usize, rest)))
+}
+
+// Status bar text after :s, the n flag only counts
+fn summary(done: usize, lines: usize, count_only: bool) -> String {
+  let plural = |n: usize, word: &str, many: &str| match n {
+    1 => format!("1 {}", word),
+    n => format!("{} {}{}", n, word, many),
+  };
+  let found = match count_only {
+    true  => plural(done, "match", "es"),
+    false => plural(done, "substitution", "s"),
+  };
+  format!("{} on {}", found, plural(lines, "line", "s"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn editor(text: &str) -> Editor {
+    Editor::new(Buffer::new(text), Area::new(0, 0, 800, 480), (10, 20), None)
+  }
+
+  fn feed(editor: &mut Editor, keys: &str) {
+    for key in keys.chars() {
+      editor.feed(key);
+    }
+  }
+
+  fn text(editor: &Editor) -> String {
+    editor.buffers.current().content.to_string()
+  }
+
+  // Each case is the text, the keys fed and the text they leave
+  fn check(cases: &[(&str, &str, &str)]) {
+    for (before, keys, after) in cases {
+      let mut ed = editor(before);
+      feed(&mut ed, keys);
+      assert_eq!(text(&ed), *after, "{:?} on {:?}", keys, before);
+    }
+  }
+
+  // Where the cursor ends up after `keys`
+  fn cursor(before: &str, keys: &str) -> usize {
+    let mut ed = editor(before);
+    feed(&mut ed, keys);
+    ed.buffers.current().cursor
+  }
+
+  #[test]
+  fn record_and_play() {
+    let mut ed = editor("a\nb\nc\nd\ne\nf\ng");
+    feed(&mut ed, "qa$a!\u{1b}jq");
+    assert_eq!(ed.register(Some('a')).map(|yanked| yanked.text), Some("$a!\u{1b}j".to_string()));
+    assert_eq!(ed.handler.recording(), None);
+    feed(&mut ed, "@a");
+    assert_eq!(text(&ed), "a!\nb!\nc\nd\ne\nf\ng");
+    feed(&mut ed, "3@a");
+    assert_eq!(text(&ed), "a!\nb!\nc!\nd!\ne!\nf\ng");
+    // @@ plays the last played register again
+    feed(&mut ed, "@@");
+    assert_eq!(text(&ed), "a!\nb!\nc!\nd!\ne!\nf!\ng");
+    assert_eq!(ed.buffers.current().row(), 6);
+    // One undo step per change the macro made
+    feed(&mut ed, "u");
+    assert_eq!(text(&ed), "a!\nb!\nc!\nd!\ne!\nf\ng");
+  }
+
+  #[test]
+  fn recording_ends_the_pending_command() {
+    let mut ed = editor("abcdef");
+    feed(&mut ed, "3qaxq");
+    assert_eq!(text(&ed), "bcdef");
+  }
+
+  #[test]
+  fn recursive_macro_stops_at_the_end() {
+    let mut ed = editor("a1\nb2\nc3\nd4");
+    feed(&mut ed, "qaxj@aq@a");
+    assert_eq!(text(&ed), "1\n2\n3\n4");
+    assert_eq!(ed.buffers.current().row(), 3);
+  }
+
+  #[test]
+  fn recursive_macro_stops_at_the_limit() {
+    let mut ed = editor("abc");
+    feed(&mut ed, "qa0@aq@a");
+    assert_eq!(ed.message, "Macro stopped after too many keys");
+    assert!(ed.queue.is_empty());
+    // Keys typed afterwards work again
+    feed(&mut ed, "x");
+    assert_eq!(text(&ed), "bc");
+  }
+
+  #[test]
+  fn normal_over_a_range() {
+    let mut ed = editor("a\nb\nc\nd");
+    feed(&mut ed, ":%normal $a;\r");
+    assert_eq!(text(&ed), "a;\nb;\nc;\nd;");
+    feed(&mut ed, ":2,3normal 0x\r");
+    assert_eq!(text(&ed), "a;\n;\n;\nd;");
+    // Unfinished keys are dropped at the end of each row
+    feed(&mut ed, ":%normal d\r");
+    assert_eq!(text(&ed), "a;\n;\n;\nd;");
+    assert_eq!(ed.handler.mode(), Mode::Edit);
+  }
+
+  #[test]
+  fn edit_reloads_the_current_file() {
+    let path = std::env::temp_dir().join(format!("editor-reload-{}", std::process::id()));
+    std::fs::write(&path, "one\n").unwrap();
+    let mut ed = editor("");
+    feed(&mut ed, &format!(":e {}\r", path.display()));
+    assert_eq!(text(&ed), "one");
+    std::fs::write(&path, "two\n").unwrap();
+    feed(&mut ed, ":e\r");
+    assert_eq!(text(&ed), "two");
+    // Changes are kept unless ! drops them
+    feed(&mut ed, "x:e\r");
+    assert_eq!(text(&ed), "wo");
+    assert_eq!(ed.message, "No write since last change (add ! to override)");
+    feed(&mut ed, ":e!\r");
+    assert_eq!(text(&ed), "two");
+    std::fs::remove_file(&path).unwrap();
+    // A buffer without a file has nothing to reload
+    let mut ed = editor("abc");
+    feed(&mut ed, ":e\r");
+    assert_eq!(ed.message, "No file name");
+  }
+
+  #[test]
+  fn split_panes_keep_their_place() {
+    let mut ed = editor("one\ntwo\nthree");
+    // The top pane stays on "three" while lines go in above it
+    feed(&mut ed, "G:split\r");
+    feed(&mut ed, "\u{17}jggizero\r\u{1b}");
+    feed(&mut ed, "\u{17}kx");
+    assert_eq!(text(&ed), "zero\none\ntwo\nhree");
+    // And when they are deleted again
+    feed(&mut ed, "\u{17}jggdd\u{17}k");
+    assert_eq!(text(&ed), "one\ntwo\nhree");
+    assert_eq!(ed.buffers.current().cursor, "one\ntwo\n".len());
+  }
+
+  #[test]
+  fn quit_keeps_unsaved_changes() {
+    let mut ed = editor("abc");
+    assert!(!ed.feed('x'));
+    assert!(!ed.feed('\u{3}'));
+    assert!(!ed.quit());
+    assert!(ed.message.starts_with("No write since last change"));
+    feed(&mut ed, ":q!");
+    assert!(ed.feed('\r'));
+    assert!(editor("abc").feed('\u{3}'));
+  }
+
+  #[test]
+  fn display_lines_take_a_count() {
+    let mut ed = editor("ijklmnop");
+    feed(&mut ed, "5gjx");
+    assert_eq!(text(&ed), "jklmnop");
+    let mut ed = editor("a\nb\nc\nd\ne");
+    feed(&mut ed, "3gjx2gkx");
+    assert_eq!(text(&ed), "a\n\nc\n\ne");
+    // Rows of a wrapped line are 80 columns wide
+    let mut ed = editor(&"x".repeat(200));
+    feed(&mut ed, ":set wrap\r2gjiy\u{1b}");
+    assert_eq!(text(&ed).find('y'), Some(160));
+  }
+
+  #[test]
+  fn scrolling_ends_the_pending_command() {
+    let mut ed = editor("abcdef");
+    feed(&mut ed, "3zzx");
+    assert_eq!(text(&ed), "bcdef");
+    feed(&mut ed, "dztx");
+    assert_eq!(text(&ed), "cdef");
+    feed(&mut ed, "2zcx");
+    assert_eq!(text(&ed), "def");
+  }
+
+  #[test]
+  fn time_travel_ends_the_pending_command() {
+    let mut ed = editor("abcdef");
+    feed(&mut ed, "x3g-x");
+    assert_eq!(text(&ed), "bcdef");
+    feed(&mut ed, "dg+x");
+    assert_eq!(text(&ed), "cdef");
+  }
+
+  #[test]
+  fn window_keys_end_the_pending_command() {
+    let mut ed = editor("abcdef");
+    feed(&mut ed, ":split\r3\u{17}jx");
+    assert_eq!(text(&ed), "bcdef");
+  }
+
+  #[test]
+  fn dot_repeats_the_last_change() {
+    check(&[
+      ("foo bar baz", "cwx\u{1b}w.", "x x baz"),
+      ("a b c d e f", "dw.", "c d e f"),
+      ("a b c d e f", "dw3.", "e f"),
+      ("a b c d e f", "2dw.", "e f"),
+      ("a b c d e f", "2dw1.", "d e f"),
+      ("a\nb\nc\nd", "dd.", "c\nd"),
+      ("abc", "ix\u{1b}.", "xxabc"),
+      ("abc", "$ax\u{1b}.", "abcxx"),
+      ("abc", "ylp.", "aaabc"),
+      ("a,b,c", "f,x;.", "abc"),
+      // Moves and yanks aren't changes
+      ("abcd", "xlyl.", "bd"),
+    ]);
+    // The repeat is one undo step
+    let mut ed = editor("foo bar");
+    feed(&mut ed, "cwxy\u{1b}w.u");
+    assert_eq!(text(&ed), "xy bar");
+  }
+
+  #[test]
+  fn dot_repeats_visual_operators() {
+    for (before, keys, after) in [
+      ("abcdef", "vld.", "ef"),
+      ("ab\ncd\nef\ngh\nij", "vjd.", "f\ngh\nij"),
+      ("a\nb\nc\nd\ne", "Vjd.", "e"),
+      ("abc\nabc\nabc\nabc", "\u{16}jld.", "\n\nabc\nabc"),
+      ("abcdef", "vlcX\u{1b}l.", "XXef"),
+      ("ab\nab\nab\nab", "\u{16}jcX\u{1b}jj.", "Xb\nXb\nXb\nXb"),
+      ("a\nb\nc", "Vj>.", "    a\n    b\nc"),
+      ("abcd", "vlUll.", "ABCD"),
+    ] {
+      let mut ed = editor(before);
+      feed(&mut ed, keys);
+      assert_eq!(text(&ed), after, "{:?}", keys);
+    }
+  }
+
+  #[test]
+  fn yank_and_put() {
+    check(&[
+      ("abc", "ylp", "aabc"),
+      ("abc", "ylP", "aabc"),
+      ("abc", "yl3p", "aaaabc"),
+      ("a\nb", "yyp", "a\na\nb"),
+      ("a\nb", "jyyP", "a\nb\nb"),
+      ("a\nb", "yy2p", "a\na\na\nb"),
+      ("foo bar", "\"ayiwwdiw\"ap", "foo foo"),
+      ("foo bar", "yiwwdiw\"0P", "foofoo "),
+      ("a\nb\nc", "ddddu\"2p", "b\na\nc"),
+    ]);
+  }
+
+  // Without an SDL clipboard "+ and "* are kept in the editor
+  #[test]
+  fn clipboard_registers() {
+    check(&[
+      ("foo bar", "\"+yiww\"+P", "foo foobar"),
+      ("a\nb", "\"*yyj\"*p", "a\nb\na"),
+      ("a\nb", ":set clipboard=unnamedplus\ryyjp", "a\nb\na"),
+    ]);
+    let mut ed = editor("a\nb");
+    feed(&mut ed, ":set clipboard=unnamedplus\ryyj\"ayy");
+    assert_eq!(ed.register(Some('+')).map(|yanked| yanked.text), Some("a\n".to_string()));
+    assert_eq!(ed.registers.get(Some('+'), "").map(|yanked| yanked.text), Some("a\n".to_string()));
+  }
+
+  #[test]
+  fn paste_in_insert_mode_is_one_undo_step() {
+    // A yank through "+ is what Ctrl-Shift-V pastes
+    let mut ed = editor("ab");
+    feed(&mut ed, "\"+yl$ixy\u{e004}z\u{1b}");
+    assert_eq!(text(&ed), "axyazb");
+    let mut undo = || {
+      feed(&mut ed, "u");
+      text(&ed)
+    };
+    assert_eq!(undo(), "axyab");
+    assert_eq!(undo(), "axyb");
+    assert_eq!(undo(), "ab");
+    // Nothing is pasted from an empty clipboard
+    let mut ed = editor("ab");
+    feed(&mut ed, "yli\u{e004}\u{1b}");
+    assert_eq!(text(&ed), "ab");
+  }
+
+  #[test]
+  fn empty_deletes_keep_the_registers() {
+    let mut ed = editor("abc\n\nz");
+    feed(&mut ed, "yiwjxp");
+    assert_eq!(text(&ed), "abc\nabc\nz");
+    feed(&mut ed, "0d0p");
+    assert_eq!(text(&ed), "abc\naabcbc\nz");
+  }
+
+  #[test]
+  fn undo_tree_keys() {
+    let mut ed = editor("abc");
+    feed(&mut ed, "xxuiZ\u{1b}");
+    assert_eq!(text(&ed), "Zbc");
+    let mut walk = |keys: &str| {
+      feed(&mut ed, keys);
+      text(&ed)
+    };
+    assert_eq!(walk("u"), "bc");
+    assert_eq!(walk("u"), "abc");
+    assert_eq!(walk("U"), "bc");
+    // Redo follows the newest branch, g- and g+ go by time
+    assert_eq!(walk("U"), "Zbc");
+    assert_eq!(walk("g-"), "c");
+    assert_eq!(walk("g-"), "bc");
+    assert_eq!(walk("g+"), "c");
+    assert_eq!(walk("g+"), "Zbc");
+    assert_eq!(walk("g+"), "Zbc");
+  }
+
+  #[test]
+  fn undo_back_to_the_saved_text_is_clean() {
+    let mut ed = editor("abc");
+    feed(&mut ed, "xx");
+    assert!(ed.buffers.current().is_dirty());
+    feed(&mut ed, "u");
+    assert!(ed.buffers.current().is_dirty());
+    feed(&mut ed, "u");
+    assert!(!ed.buffers.current().is_dirty());
+    feed(&mut ed, "U");
+    assert!(ed.buffers.current().is_dirty());
+  }
+
+  #[test]
+  fn word_motions() {
+    let text = "foo.bar baz\n  qux(x) end";
+    assert_eq!(cursor(text, "w"), 3);
+    assert_eq!(cursor(text, "ww"), 4);
+    assert_eq!(cursor(text, "3w"), 8);
+    assert_eq!(cursor(text, "W"), 8);
+    assert_eq!(cursor(text, "2W"), 14);
+    assert_eq!(cursor(text, "e"), 2);
+    assert_eq!(cursor(text, "E"), 6);
+    assert_eq!(cursor(text, "$b"), 8);
+    assert_eq!(cursor(text, "$2b"), 4);
+    assert_eq!(cursor(text, "jB"), 8);
+    assert_eq!(cursor(text, "wwge"), 3);
+    assert_eq!(cursor(text, "Wge"), 6);
+    assert_eq!(cursor(text, "WgE"), 6);
+  }
+
+  #[test]
+  fn sentence_and_paragraph_motions() {
+    let text = "One two. Three four.  Five\n\nSix.\nSeven";
+    assert_eq!(cursor(text, ")"), 9);
+    assert_eq!(cursor(text, "2)"), 22);
+    assert_eq!(cursor(text, "2)("), 9);
+    assert_eq!(cursor(text, "}"), 27);
+    assert_eq!(cursor(text, "}}"), text.len() - 1);
+    assert_eq!(cursor(text, "G{"), 27);
+  }
+
+  #[test]
+  fn word_operators() {
+    check(&[
+      ("foo bar baz", "dw", "bar baz"),
+      ("foo bar baz", "2dw", "baz"),
+      ("foo bar baz", "d2w", "baz"),
+      ("foo bar baz", "cwx\u{1b}", "x bar baz"),
+      ("foo bar baz", "wcwx\u{1b}", "foo x baz"),
+      ("foo bar baz", "de", " bar baz"),
+      ("foo bar baz", "$db", "foo bar z"),
+      ("foo.bar baz", "dW", "baz"),
+      ("a\nb\n\nc", "d}", "\nc"),
+      ("a\n\nb\nc", "Gd{", "a\nc"),
+    ]);
+  }
+
+  #[test]
+  fn operator_grammar() {
+    check(&[
+      ("a b c d e f g h", "2d3w", "g h"),
+      ("foo bar", "w\"ayiw0\"aP", "barfoo bar"),
+      ("foo bar", "wc$x\u{1b}", "foo x"),
+      ("a\nb\nc\nd", "2yyGp", "a\nb\nc\nd\na\nb"),
+      ("a\nb\nc\nd", "d2j", "d"),
+      ("abc", "\"_xp", "bc"),
+    ]);
+  }
+
+  #[test]
+  fn text_objects() {
+    check(&[
+      ("foo bar baz", "wdiw", "foo  baz"),
+      ("foo bar baz", "wdaw", "foo baz"),
+      ("foo bar baz", "$daw", "foo bar"),
+      ("a.b-c d", "diW", " d"),
+      ("x = \"a b\";", "ci\"y\u{1b}", "x = \"y\";"),
+      ("x = 'a b';", "fada'", "x =;"),
+      ("f(a, (b), c)", "fbdi(", "f(a, (), c)"),
+      ("f(a, (b), c)", "fbd2i(", "f()"),
+      ("f(a, (b), c)", "fada(", "f"),
+      ("{ x [1] }", "f1di[", "{ x [] }"),
+      ("a <b> c", "fbda<", "a  c"),
+      ("<a><b>x</b></a>", "fxdit", "<a><b></b></a>"),
+      ("<a><b>x</b></a>", "fxdat", "<a></a>"),
+      ("<a><b>x</b></a>", "fxd2it", "<a></a>"),
+      ("One. Two three. Four.", "fTdis", "One.  Four."),
+      ("One. Two three. Four.", "fTdas", "One. Four."),
+      ("a\nb\n\nc", "dip", "\nc"),
+      ("a\nb\n\nc", "dap", "c"),
+    ]);
+    // yap yanks the paragraph linewise
+    let mut ed = editor("a\nb\n\nc");
+    feed(&mut ed, "yapGp");
+    assert_eq!(text(&ed), "a\nb\n\nc\na\nb\n");
+    // Visual mode selects the object
+    check(&[("foo bar baz", "wviwd", "foo  baz"), ("f(a, b)", "favi(d", "f()"), ("f((a), b)", "fav2i(d", "f()")]);
+  }
+
+  #[test]
+  fn find_motions() {
+    let text = "a(b) c(d) e\nf(g)";
+    assert_eq!(cursor(text, "f("), 1);
+    assert_eq!(cursor(text, "2f("), 6);
+    assert_eq!(cursor(text, "t("), 0);
+    assert_eq!(cursor(text, "lt("), 5);
+    assert_eq!(cursor(text, "$F("), 6);
+    assert_eq!(cursor(text, "$T("), 7);
+    // ; and , repeat the last find, t skips the char right next to it
+    assert_eq!(cursor(text, "f(;"), 6);
+    assert_eq!(cursor(text, "f(;,"), 1);
+    assert_eq!(cursor(text, "t(;"), 5);
+    assert_eq!(cursor(text, "$T(;"), 2);
+    // Finds stay on the cursor's row
+    assert_eq!(cursor(text, "2f(;"), 6);
+    assert_eq!(cursor(text, "fg"), 0);
+    check(&[
+      ("f(a, b) + c", "dt)", ") + c"),
+      ("f(a, (b)) + c", "fad2f)", "f( + c"),
+      ("f(a, b) + c", "fadf)", "f( + c"),
+      ("f(a, b) + c", "$dF(", "fc"),
+      ("f(a, b) + c", "$dT(", "f(c"),
+      ("a,b,c,d", "dt,.", ",c,d"),
+      ("a,b,c,d", "df,;.", "bd"),
+    ]);
+  }
+
+  #[test]
+  fn visual_modes() {
+    check(&[
+      ("abc def", "vlld", " def"),
+      ("abc def", "wvhhd", "abef"),
+      ("abc def", "vey$p", "abc defabc"),
+      ("abc def", "vwc-\u{1b}", "-ef"),
+      ("abc def", "lvlohd", " def"),
+      ("a\nb\nc", "Vjd", "c"),
+      ("a\nb\nc", "jVkyGp", "a\nb\nc\na\nb"),
+      ("a\nb\nc", "vjV>", "  a\n  b\nc"),
+      ("    a\n  b", "Vj<", "  a\nb"),
+      ("aB\ncD", "vj~", "Ab\nCD"),
+      ("aB\ncD", "Vju", "ab\ncd"),
+      ("aB\ncD", "vlU", "AB\ncD"),
+      ("a\nb\nc", "VjJ", "a b\nc"),
+      ("abc\nabc", "vjrx", "xxx\nxbc"),
+      // v and V switch kinds, the same key again ends Visual mode
+      ("a\nb", "Vvd", "\nb"),
+      ("abc", "vvx", "bc"),
+      ("abc", "v\u{1b}x", "bc"),
+    ]);
+  }
+
+  #[test]
+  fn visual_block() {
+    check(&[
+      ("abcd\nabcd\nabcd", "l\u{16}jld", "ad\nad\nabcd"),
+      ("abcd\nabcd", "l\u{16}jly$p", "abcdbc\nabcdbc"),
+      ("abcd\nab\nabcd", "ll\u{16}jjIx\u{1b}", "abxcd\nab\nabxcd"),
+      ("abcd\nab\nabcd", "ll\u{16}jjAx\u{1b}", "abcxd\nab x\nabcxd"),
+      ("ab\nabcd", "\u{16}j$Ax\u{1b}", "abx\nabcdx"),
+      ("abcd\nabcd", "l\u{16}jlcX\u{1b}", "aXd\naXd"),
+      ("abcd\nabcd", "l\u{16}jlU", "aBCd\naBCd"),
+    ]);
+  }
+
+  #[test]
+  fn caret_is_charwise() {
+    let mut ed = editor("  foo bar\nnext");
+    feed(&mut ed, "6ld^");
+    assert_eq!(text(&ed), "  bar\nnext");
+    assert_eq!(ed.buffers.current().col(), 2);
+
+    let mut ed = editor("  foo bar\nnext");
+    feed(&mut ed, "6l3y^");
+    assert_eq!(ed.register(None).map(|yanked| yanked.text), Some("foo ".to_string()));
+    assert_eq!(text(&ed), "  foo bar\nnext");
+
+    let mut ed = editor("  foo bar\nnext");
+    feed(&mut ed, "6lc^x\u{1b}");
+    assert_eq!(text(&ed), "  xbar\nnext");
+  }
+
+  #[test]
+  fn star_follows_the_regex_syntax() {
+    for set in ["", ":set regex=rust\r"] {
+      let mut ed = editor("foo foobar foo");
+      feed(&mut ed, set);
+      feed(&mut ed, "*");
+      assert_eq!(ed.buffers.current().col(), 11, "{}", set);
+      assert_eq!(ed.message, "");
+      feed(&mut ed, "#");
+      assert_eq!(ed.buffers.current().col(), 0, "{}", set);
+    }
+  }
+
+  #[test]
+  fn underscore_is_linewise() {
+    let mut ed = editor("  foo\nbar\nbaz");
+    feed(&mut ed, "d2_");
+    assert_eq!(text(&ed), "baz");
+  }
+}
@@ -1,26 +1,61 @@
-use std::fmt::write;
-use std::fmt::Display;
-
-use sdl2::EventPump;
 use sdl2::event::Event;
-use sdl2::Sdl;
 use sdl2::keyboard::Keycode;
 use sdl2::keyboard::Mod;
-use crate::motion::Motion;
+use std::fmt::Display;
+use crate::motion::{Motion, Pending, Target, Dest, Action, Seek, Object, Visual};
+use crate::layout::Scroll;
 use crate::motion::{MOVE_D, MOVE_U, MOVE_L, MOVE_R, CUTBACK};
 
+// Keys without a character of their own are control characters or come
+// from Unicode's private use area, so any key sequence fits in a String
+// and can be kept in a register.
+pub const ESC: char = '\u{1b}';
+pub const ENTER: char = '\r';
+pub const BACKSPACE: char = '\u{8}';
+pub const TAB: char = '\t';
+pub const LEFT: char = '\u{e000}';
+pub const RIGHT: char = '\u{e001}';
+pub const UP: char = '\u{e002}';
+pub const DOWN: char = '\u{e003}';
+// Ctrl-Shift-V
+pub const PASTE: char = '\u{e004}';
+pub const WHEEL_UP: char = '\u{e005}';
+pub const WHEEL_DOWN: char = '\u{e006}';
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Mode {
-  Insert = 0,
-  Edit = 1,
+  Insert,
+  Edit,
+  Visual(Visual),
+}
+
+impl Display for Mode {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      Mode::Insert => "Insert",
+      Mode::Edit => "Edit",
+      Mode::Visual(Visual::Char) => "Visual",
+      Mode::Visual(Visual::Line) => "Visual Line",
+      Mode::Visual(Visual::Block) => "Visual Block",
+    };
+    write!(f, "{}", name)
+  }
 }
 
 pub struct EventHandler {
-  event_pump: EventPump,
   mode: Mode,
-  motion: Motion,
+  pending: Pending,
   command: String,
   cmd_active: bool,
+  // What the command line is for: ':', or '/' and '?' searching
+  prompt: char,
+  // Count typed before / or ?
+  search_count: usize,
+  prefix: Option<char>,
+  // Register being recorded into with q and the keys typed so far
+  recording: Option<(char, String)>,
+  // Register @@ plays
+  last_macro: Option<char>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -35,150 +70,571 @@ pub enum HandleResult {
   NewlineUp,
   Undo,
   Redo,
+  Earlier,
+  Later,
+  Window(char),
+  Scroll(Scroll),
+  DisplayLine(isize),
   PrintHistory,
   Command,
+  // Visual mode was entered, left or changed kind
+  Visual,
+  OtherEnd,
+  // > and <, signed number of shifts
+  Shift(isize),
+  // Visual-block I and A, true for A
+  BlockInsert(bool),
+  // p and P: register, count and true for P
+  Put(Option<char>, usize, bool),
+  // Ctrl-Shift-V in Insert mode
+  Paste,
+  // ., with a count replacing the one of the repeated change
+  Repeat(Option<usize>),
+  // q ended a recording, the keys are taken with take_recording
+  Recorded,
+  // @: register and count
+  Play(char, usize),
+  // Enter on the search line: true for /, and count
+  Search(bool, usize),
+  // n and N: true for N, and count
+  SearchNext(bool, usize),
+  // * and #: true for *, and count
+  SearchWord(bool, usize),
+  // Up and Down on the search line
+  History(isize),
+  // zc zo za zR and zM
+  Fold(char),
 }
 
 impl EventHandler {
-  pub fn new(context: &Sdl) -> Result<Self, String> {
-    Ok(EventHandler {
-      event_pump: context.event_pump()?,
+  pub fn new() -> Self {
+    EventHandler {
       mode: Mode::Edit,
-      motion: Motion::new(),
+      pending: Pending::new(),
       command: String::new(),
       cmd_active: false,
-    })
+      prompt: ':',
+      search_count: 1,
+      prefix: None,
+      recording: None,
+      last_macro: None,
+    }
   }
 
   /* 
   Jos palautus vaatii tekstiä ( esim. jos Motion[0] == Insert ), 
   lisätään se  m_buffiin
   */
-  pub fn handle(&mut self, m_buff: &mut String) -> HandleResult {
+  pub fn handle(&mut self, key: char, m_buff: &mut String) -> HandleResult {
     let mut result = HandleResult::None;
-    if let Some(event) = self.event_pump.poll_event() {
-      match event {
-        Event::TextInput { text, .. } => {
-          if self.mode == Mode::Edit {
-            if self.cmd_active {
-              self.command.push_str(&text);
-              return result;
-            }
-            match text.as_str() {
-              "a" => {
-                self.mode = Mode::Insert;
-              },
-              "i" => {
-                self.mode = Mode::Insert;
-                result = HandleResult::Motion(MOVE_L)
-              },
-              "o" => {
-                self.mode = Mode::Insert;
-                result = HandleResult::NewlineNoSplit;
-              },
-              "O" => {
-                self.mode = Mode::Insert;
-                result = HandleResult::NewlineUp;
-              }
-              "u" => {
-                result = HandleResult::Undo;
-              }
-              "U" => {
-                result = HandleResult::Redo;
-              }
-              "H" => {
-                result = HandleResult::PrintHistory;
-              }
-              ":" => {
-                self.cmd_active = true;
-              }
-              _ => if let Some(motion) = self.motion.push(text.chars().nth(0)) {
-                result = HandleResult::Motion(motion);
-              }
-            }
-          } else {
-            result = HandleResult::Insert;
-            m_buff.push_str(&text)
+    match key {
+      ESC => {
+        self.command.clear();
+        self.pending.reset();
+        self.prefix = None;
+        result = match self.mode {
+          Mode::Edit => {
+            self.cmd_active = false;
+            HandleResult::Motion(MOVE_L)
+          }
+          Mode::Insert => {
+            self.mode = Mode::Edit;
+            HandleResult::SetEditMode
+          }
+          Mode::Visual(_) => {
+            self.cmd_active = false;
+            self.mode = Mode::Edit;
+            HandleResult::Visual
           }
+        }
+      },
+
+      ENTER => match self.mode {
+        Mode::Insert    => result = {
+          HandleResult::NewlineSplit
         },
+        _ => {
+          if self.cmd_active && self.prompt != ':' {
+            m_buff.push_str(&self.command);
+            self.command.clear();
+            result = HandleResult::Search(self.prompt == '/', self.search_count)
+          } else if !self.command.is_empty() {
+            m_buff.push_str(&self.command);
+            self.command.clear();
+            result = HandleResult::Command
+          }
+          self.cmd_active = false;
+        }
+      },
 
-        Event::KeyDown { keycode, keymod, .. } => {
-          match keycode {
-            Some(Keycode::Escape)    => {
-              self.command.clear();
-              result = match self.mode {
-                Mode::Edit => {
-                  self.cmd_active = false;
-                  HandleResult::Motion(MOVE_L)
-                }
-                Mode::Insert => {
-                  self.mode = Mode::Edit;
-                  HandleResult::SetEditMode
-                }
-              }
-            },
+      BACKSPACE => {
+        match self.mode {
+          Mode::Insert  => result = HandleResult::Motion(CUTBACK),
+          _             => {
+            if self.cmd_active && self.command.pop().is_none() {
+              self.cmd_active = false;
+            } else {
+              result = HandleResult::Motion(MOVE_L)
+            }
+          }
+        }
+      },
 
-            Some(Keycode::Return) => match self.mode {
-              Mode::Insert    => result = {
-                HandleResult::NewlineSplit
-              },
-              Mode::Edit => {
-                if !self.command.is_empty() {
-                  m_buff.push_str(&self.command);
-                  self.command.clear();
-                  result = HandleResult::Command
-                }
-                self.cmd_active = false;
-              }
-            },
+      TAB       => result = match self.mode { 
+        Mode::Insert => {
+          m_buff.push_str("  ");
+          HandleResult::Insert
+        },
+        _            => HandleResult::None
+      },
 
-            Some(Keycode::Backspace) => {
-              match self.mode {
-                Mode::Insert  => result = HandleResult::Motion(CUTBACK),
-                Mode::Edit    => {
-                  if self.cmd_active && self.command.pop() == None {
-                    self.cmd_active = false;
-                  } else {
-                    result = HandleResult::Motion(MOVE_L)
-                  }
-                }
-              }
-            },
+      RIGHT     => result = HandleResult::Motion(MOVE_R),
+      LEFT      => result = HandleResult::Motion(MOVE_L),
+      UP   if self.cmd_active && self.prompt != ':' => result = HandleResult::History(-1),
+      DOWN if self.cmd_active && self.prompt != ':' => result = HandleResult::History(1),
+      UP        => result = HandleResult::Motion(MOVE_U),
+      DOWN      => result = HandleResult::Motion(MOVE_D),
+      PASTE if self.mode == Mode::Insert => result = HandleResult::Paste,
+      WHEEL_UP   => result = HandleResult::Scroll(Scroll::Line(-3)),
+      WHEEL_DOWN => result = HandleResult::Scroll(Scroll::Line(3)),
+      // Ctrl-c
+      '\u{3}' => result = HandleResult::Quit,
+      // Ctrl-w
+      '\u{17}' if self.mode == Mode::Edit => {
+        self.prefix = Some('\u{17}')
+      },
+      // Ctrl-v
+      '\u{16}' if self.mode != Mode::Insert => {
+        self.pending.reset();
+        self.mode = match self.mode {
+          Mode::Visual(Visual::Block) => Mode::Edit,
+          _ => Mode::Visual(Visual::Block),
+        };
+        result = HandleResult::Visual
+      },
+      key if key.is_control() && self.mode != Mode::Insert => {
+        result = match key {
+          '\u{5}'  => HandleResult::Scroll(Scroll::Line(1)),
+          '\u{19}' => HandleResult::Scroll(Scroll::Line(-1)),
+          '\u{4}'  => HandleResult::Scroll(Scroll::HalfPage(1)),
+          '\u{15}' => HandleResult::Scroll(Scroll::HalfPage(-1)),
+          '\u{6}'  => HandleResult::Scroll(Scroll::Page(1)),
+          '\u{2}'  => HandleResult::Scroll(Scroll::Page(-1)),
+          _ => result,
+        }
+      },
+      key if key.is_control() || ('\u{e000}'..='\u{f8ff}').contains(&key) => {},
+      key if self.mode == Mode::Insert => {
+        result = HandleResult::Insert;
+        m_buff.push(key)
+      },
+      key => result = self.handle_text(key),
+    }
+    result
+  }
 
-            Some(Keycode::Tab)       => result = match self.mode { 
-              Mode::Insert => {
-                m_buff.push_str("  ");
-                HandleResult::Insert
-              },
-              Mode::Edit   => HandleResult::None
+  // Text typed outside Insert mode
+  fn handle_text(&mut self, key: char) -> HandleResult {
+    let mut result = HandleResult::None;
+    let text = key.to_string();
+    if self.cmd_active {
+      self.command.push(key);
+      return result;
+    }
+    if self.pending.is_awaiting() {
+      return match self.pending.push(Some(key)) {
+        Some(motion) => self.run(motion),
+        None => result,
+      }
+    }
+    if let Some(prefix) = self.prefix.take() {
+      return match (prefix, key) {
+        ('g', '-' | '+') => {
+          self.pending.reset();
+          match key {
+            '-' => HandleResult::Earlier,
+            _   => HandleResult::Later,
+          }
+        },
+        ('g', 'g') => {
+          let row = self.pending.take_count().unwrap_or(1);
+          self.pending.finish(Target::Dest(Dest::Row(row))).map_or(result, |m| self.run(m))
+        },
+        ('g', 'e' | 'E') => {
+          let seek = match key {
+            'e' => Seek::WordEnd,
+            _   => Seek::BigWordEnd,
+          };
+          self.pending.finish(Target::Dest(Dest::Seek(seek, false))).map_or(result, |m| self.run(m))
+        },
+        ('g', 'j' | 'k') => {
+          let n = self.pending.take_count().unwrap_or(1) as isize;
+          self.pending.reset();
+          HandleResult::DisplayLine(if key == 'j' { n } else { -n })
+        },
+        ('z', 't' | 'z' | 'b') => {
+          self.pending.reset();
+          HandleResult::Scroll(match key {
+            't' => Scroll::Top,
+            'z' => Scroll::Center,
+            _   => Scroll::Bottom,
+          })
+        },
+        ('z', 'c' | 'o' | 'a' | 'R' | 'M') => {
+          self.pending.reset();
+          HandleResult::Fold(key)
+        },
+        ('r', c) => match self.mode {
+          Mode::Visual(visual) => self.operate(Action::Replace(c), visual),
+          _ => result
+        },
+        // Ctrl-w
+        ('\u{17}', c) => {
+          self.pending.reset();
+          HandleResult::Window(c)
+        },
+        ('q', c) if c.is_ascii_alphanumeric() || c == '"' => {
+          self.pending.reset();
+          self.recording = Some((c, String::new()));
+          result
+        },
+        // @@ plays the register played last
+        ('@', c) => {
+          let count = self.pending.take_count().unwrap_or(1);
+          self.pending.reset();
+          match if c == '@' { self.last_macro } else { Some(c) } {
+            Some(register) => {
+              self.last_macro = Some(register);
+              HandleResult::Play(register, count)
             },
-
-            Some(Keycode::Right)     => result = HandleResult::Motion(MOVE_R),
-            Some(Keycode::Left)      => result = HandleResult::Motion(MOVE_L),
-            Some(Keycode::Up)        => result = HandleResult::Motion(MOVE_U),
-            Some(Keycode::Down)      => result = HandleResult::Motion(MOVE_D),
-            Some(Keycode::C)         => if keymod.contains(Mod::LCTRLMOD) {result = HandleResult::Quit},
-
-            _ => {}
+            None => result,
           }
         },
-        Event::Quit { .. } => result = HandleResult::Quit,
-        _=> {}
+        _ => result
+      }
+    }
+    if let Mode::Visual(visual) = self.mode {
+      if let Some(result) = self.handle_visual(visual, &text) {
+        return result
+      }
+    }
+    match text.as_str() {
+      "a" if self.pending.operator().is_none() => {
+        self.mode = Mode::Insert;
+        result = HandleResult::Motion(MOVE_R)
+      },
+      "i" if self.pending.operator().is_none() => {
+        self.mode = Mode::Insert;
+      },
+      "o" => {
+        self.mode = Mode::Insert;
+        result = HandleResult::NewlineNoSplit;
+      },
+      "O" => {
+        self.mode = Mode::Insert;
+        result = HandleResult::NewlineUp;
+      }
+      "u" => {
+        result = HandleResult::Undo;
+      }
+      "U" => {
+        result = HandleResult::Redo;
+      }
+      "H" => {
+        result = HandleResult::PrintHistory;
+      }
+      "p" | "P" if self.pending.operator().is_none() => {
+        let count = self.pending.take_count().unwrap_or(1);
+        result = HandleResult::Put(self.pending.register(), count, text == "P");
+        self.pending.reset();
+      }
+      "." if self.pending.operator().is_none() => {
+        result = HandleResult::Repeat(self.pending.take_count());
+        self.pending.reset();
+      }
+      "n" | "N" | "*" | "#" if self.pending.operator().is_none() => {
+        let count = self.pending.take_count().unwrap_or(1);
+        self.pending.reset();
+        result = match key {
+          'n' | 'N' => HandleResult::SearchNext(key == 'N', count),
+          _ => HandleResult::SearchWord(key == '*', count),
+        };
+      }
+      "q" if self.pending.operator().is_none() => match self.recording {
+        Some(_) => {
+          self.pending.reset();
+          result = HandleResult::Recorded;
+        },
+        None => self.prefix = Some('q'),
+      }
+      "@" if self.pending.operator().is_none() => {
+        self.prefix = Some('@');
+      }
+      "v" | "V" => {
+        self.pending.reset();
+        self.mode = match text.as_str() {
+          "v" => Mode::Visual(Visual::Char),
+          _   => Mode::Visual(Visual::Line),
+        };
+        result = HandleResult::Visual;
+      }
+      "g" | "z" => {
+        self.prefix = Some(key);
+      }
+      ":" | "/" | "?" => {
+        self.search_count = self.pending.take_count().unwrap_or(1);
+        self.pending.reset();
+        self.cmd_active = true;
+        self.prompt = key;
+      }
+      _ => if let Some(motion) = self.pending.push(Some(key)) {
+        result = self.run(motion);
       }
-
     }
     result
   }
 
+  // A finished command, c leaves the editor in Insert mode
+  fn run(&mut self, motion: Motion) -> HandleResult {
+    if motion.verb == Action::Change {
+      self.mode = Mode::Insert;
+    }
+    // ip and ap select whole rows
+    if let (Mode::Visual(_), Target::Object(Object::Paragraph, _)) = (self.mode, motion.target) {
+      self.mode = Mode::Visual(Visual::Line);
+    }
+    HandleResult::Motion(motion)
+  }
+
+  // Keys working on the selection in Visual mode, None for the ones
+  // handled like in Edit mode
+  fn handle_visual(&mut self, visual: Visual, text: &str) -> Option<HandleResult> {
+    let result = match text {
+      "v" | "V" => {
+        let kind = match text {
+          "v" => Visual::Char,
+          _   => Visual::Line,
+        };
+        self.pending.reset();
+        self.mode = match kind == visual {
+          true  => Mode::Edit,
+          false => Mode::Visual(kind),
+        };
+        HandleResult::Visual
+      },
+      "o" | "O" => HandleResult::OtherEnd,
+      "i" | "a" => {
+        self.pending.select_object(text == "i");
+        HandleResult::None
+      },
+      "d" | "x" => self.operate(Action::Cut, visual),
+      "D" | "X" => self.operate(Action::Cut, Visual::Line),
+      "c" | "s" => self.operate(Action::Change, visual),
+      "C" | "S" | "R" => self.operate(Action::Change, Visual::Line),
+      "y" => self.operate(Action::Yank, visual),
+      "Y" => self.operate(Action::Yank, Visual::Line),
+      "J" => self.operate(Action::Join, visual),
+      "~" => self.operate(Action::SwapCase, visual),
+      "u" => self.operate(Action::Lowercase, visual),
+      "U" => self.operate(Action::Uppercase, visual),
+      "r" => {
+        self.prefix = Some('r');
+        HandleResult::None
+      },
+      ">" | "<" => {
+        let n = self.pending.take_count().unwrap_or(1) as isize;
+        self.pending.reset();
+        self.mode = Mode::Edit;
+        HandleResult::Shift(if text == ">" { n } else { -n })
+      },
+      // The command line starts with the range of the selected rows
+      ":" => {
+        self.pending.reset();
+        self.mode = Mode::Edit;
+        self.cmd_active = true;
+        self.prompt = ':';
+        self.command = "'<,'>".to_string();
+        HandleResult::Visual
+      },
+      "I" | "A" if visual == Visual::Block => {
+        self.pending.reset();
+        self.mode = Mode::Insert;
+        HandleResult::BlockInsert(text == "A")
+      },
+      _ => return None,
+    };
+    Some(result)
+  }
+
+  fn operate(&mut self, verb: Action, visual: Visual) -> HandleResult {
+    let motion = self.pending.operate(verb, visual);
+    self.mode = Mode::Edit;
+    self.run(motion)
+  }
+
   pub fn command(&self) -> &str {
     &self.command
   }
 
+  pub fn set_command(&mut self, command: &str) {
+    self.command = command.to_string();
+  }
+
   pub fn cmd_active(&self) -> bool {
     self.cmd_active
   }
+
+  pub fn prompt(&self) -> char {
+    self.prompt
+  }
+
+  // Some(true) while a pattern for / is typed, Some(false) for ?
+  pub fn searching(&self) -> Option<bool> {
+    match (self.cmd_active, self.prompt) {
+      (true, '/') => Some(true),
+      (true, '?') => Some(false),
+      _ => None,
+    }
+  }
   
   pub fn mode(&self) -> Mode {
     self.mode
   }
-}
\ No newline at end of file
+
+  // Drops a half typed command, used after :normal runs its keys
+  pub fn reset(&mut self) {
+    self.pending.reset();
+    self.prefix = None;
+    self.command.clear();
+    self.cmd_active = false;
+  }
+
+  // Keys typed while recording a macro are passed here before they are
+  // handled, keys played from registers are not
+  pub fn record(&mut self, key: char) {
+    if let Some((_, keys)) = &mut self.recording {
+      if key != WHEEL_UP && key != WHEEL_DOWN {
+        keys.push(key);
+      }
+    }
+  }
+
+  pub fn recording(&self) -> Option<char> {
+    self.recording.as_ref().map(|(register, _)| *register)
+  }
+
+  // Register and keys of the recording q ended, without that q
+  pub fn take_recording(&mut self) -> Option<(char, String)> {
+    let (register, mut keys) = self.recording.take()?;
+    if keys.ends_with('q') {
+      keys.pop();
+    }
+    Some((register, keys))
+  }
+}
+
+// Keys an SDL event stands for. Typed text comes as TextInput, the keys
+// producing no text as KeyDown.
+pub fn keys(event: &Event) -> Vec<char> {
+  let key = match event {
+    Event::TextInput { text, .. } => return text.chars().collect(),
+    Event::MouseWheel { y, .. } => {
+      let key = if *y > 0 { WHEEL_UP } else { WHEEL_DOWN };
+      return vec![key; y.unsigned_abs() as usize]
+    },
+    Event::KeyDown { keycode: Some(keycode), keymod, .. } => match *keycode {
+      Keycode::Escape    => ESC,
+      Keycode::Return    => ENTER,
+      Keycode::Backspace => BACKSPACE,
+      Keycode::Tab       => TAB,
+      Keycode::Right     => RIGHT,
+      Keycode::Left      => LEFT,
+      Keycode::Up        => UP,
+      Keycode::Down      => DOWN,
+      Keycode::V if ctrl(*keymod) && shift(*keymod) => PASTE,
+      // Ctrl with a letter gives the letter's control character
+      keycode if ctrl(*keymod) => match char::from_u32(keycode as u32) {
+        Some(c) if c.is_ascii_lowercase() => (c as u8 & 0x1f) as char,
+        _ => return Vec::new(),
+      },
+      _ => return Vec::new(),
+    },
+    _ => return Vec::new(),
+  };
+  vec![key]
+}
+
+fn ctrl(keymod: Mod) -> bool {
+  keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)
+}
+
+fn shift(keymod: Mod) -> bool {
+  keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Results of handling each of `keys`, dropping the Nones
+  fn handle(handler: &mut EventHandler, keys: &str) -> Vec<HandleResult> {
+    let mut m_buff = String::new();
+    keys
+      .chars()
+      .map(|key| handler.handle(key, &mut m_buff))
+      .filter(|result| *result != HandleResult::None)
+      .collect()
+  }
+
+  #[test]
+  fn visual_modes_switch_and_end() {
+    let mut handler = EventHandler::new();
+    assert_eq!(handle(&mut handler, "v"), [HandleResult::Visual]);
+    assert_eq!(handler.mode(), Mode::Visual(Visual::Char));
+    handle(&mut handler, "V");
+    assert_eq!(handler.mode(), Mode::Visual(Visual::Line));
+    handle(&mut handler, "\u{16}");
+    assert_eq!(handler.mode(), Mode::Visual(Visual::Block));
+    handle(&mut handler, "\u{16}");
+    assert_eq!(handler.mode(), Mode::Edit);
+    handle(&mut handler, "vv");
+    assert_eq!(handler.mode(), Mode::Edit);
+    handle(&mut handler, "V");
+    assert_eq!(handle(&mut handler, &ESC.to_string()), [HandleResult::Visual]);
+    assert_eq!(handler.mode(), Mode::Edit);
+  }
+
+  #[test]
+  fn visual_operators_take_the_selection() {
+    let mut handler = EventHandler::new();
+    let selection = |verb, visual| HandleResult::Motion(Motion::new(verb, Target::Selection(visual)));
+    handle(&mut handler, "v");
+    assert_eq!(handle(&mut handler, "d"), [selection(Action::Cut, Visual::Char)]);
+    assert_eq!(handler.mode(), Mode::Edit);
+    handle(&mut handler, "V");
+    assert_eq!(handle(&mut handler, "c"), [selection(Action::Change, Visual::Line)]);
+    assert_eq!(handler.mode(), Mode::Insert);
+    handle(&mut handler, "\u{1b}\u{16}");
+    assert_eq!(handle(&mut handler, "rx"), [selection(Action::Replace('x'), Visual::Block)]);
+    handle(&mut handler, "\u{16}");
+    assert_eq!(handle(&mut handler, "A"), [HandleResult::BlockInsert(true)]);
+    assert_eq!(handler.mode(), Mode::Insert);
+    handle(&mut handler, "\u{1b}V");
+    assert_eq!(handle(&mut handler, "2>"), [HandleResult::Shift(2)]);
+    assert_eq!(handler.mode(), Mode::Edit);
+  }
+
+  #[test]
+  fn motions_in_visual_mode_move() {
+    let mut handler = EventHandler::new();
+    handle(&mut handler, "v");
+    assert_eq!(handle(&mut handler, "2w"), [HandleResult::Motion(Motion {
+      count: Some(2),
+      ..Motion::new(Action::Move, Target::Dest(Dest::Seek(Seek::Word, true)))
+    })]);
+    assert_eq!(handle(&mut handler, "iw"), [HandleResult::Motion(Motion::new(Action::Move, Target::Object(Object::Word, true)))]);
+    // ip makes the selection linewise
+    handle(&mut handler, "ap");
+    assert_eq!(handler.mode(), Mode::Visual(Visual::Line));
+  }
+}
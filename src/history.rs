@@ -0,0 +1,235 @@
+use std::fmt::Display;
+
+//------------------------------------------------------
+// Delta
+//------------------------------------------------------
+// One undo step: the chars `removed` at `from` were replaced with
+// `inserted`. Only the changed stretch is kept, not the whole text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Delta {
+  pub from: usize,
+  pub removed: String,
+  pub inserted: String,
+}
+
+impl Delta {
+  // The step that takes this one back
+  pub fn inverse(&self) -> Delta {
+    Delta {
+      from: self.from,
+      removed: self.inserted.clone(),
+      inserted: self.removed.clone(),
+    }
+  }
+}
+
+//------------------------------------------------------
+// StateHistory
+//------------------------------------------------------
+// Undo tree. Every pushed step becomes a child of the current node,
+// so undoing and then editing starts a new branch instead of throwing
+// the old one away. Node indices double as sequence numbers, which
+// makes g- / g+ a walk through time rather than through the tree.
+struct Node {
+  // Step from the parent to this node, empty for the root
+  delta: Delta,
+  parent: Option<usize>,
+  // Branch followed by redo
+  redo_child: Option<usize>,
+}
+
+pub struct StateHistory {
+  nodes: Vec<Node>,
+  current: usize,
+  // Node matching the file on disk
+  saved: usize,
+}
+
+impl StateHistory {
+  pub fn new() -> Self {
+    StateHistory {
+      nodes: vec![Node {
+        delta: Delta::default(),
+        parent: None,
+        redo_child: None,
+      }],
+      current: 0,
+      saved: 0,
+    }
+  }
+
+  pub fn push(&mut self, delta: Delta) {
+    if delta.removed == delta.inserted {
+      return
+    }
+    let idx = self.nodes.len();
+    self.nodes.push(Node {
+      delta,
+      parent: Some(self.current),
+      redo_child: None,
+    });
+    self.nodes[self.current].redo_child = Some(idx);
+    self.current = idx;
+  }
+
+  // Undo
+  pub fn previous(&mut self) -> Option<Vec<Delta>> {
+    let parent = self.nodes[self.current].parent?;
+    Some(self.jump(parent))
+  }
+
+  // Redo
+  pub fn next(&mut self) -> Option<Vec<Delta>> {
+    let child = self.nodes[self.current].redo_child?;
+    Some(self.jump(child))
+  }
+
+  // g-
+  pub fn earlier(&mut self) -> Option<Vec<Delta>> {
+    match self.current {
+      0 => None,
+      n => Some(self.jump(n - 1)),
+    }
+  }
+
+  // g+
+  pub fn later(&mut self) -> Option<Vec<Delta>> {
+    match self.current + 1 < self.nodes.len() {
+      true  => Some(self.jump(self.current + 1)),
+      false => None,
+    }
+  }
+
+  // The text was written, the current node matches the file
+  pub fn mark_saved(&mut self) {
+    self.saved = self.current;
+  }
+
+  pub fn is_saved(&self) -> bool {
+    self.saved == self.current
+  }
+
+  // Moves to any node and returns the steps to apply, in order: back up
+  // to the common ancestor, then down to the target. Redo is pointed
+  // along the way down so that `next()` retraces it. A parent always
+  // has a lower index than its children, which finds the ancestor.
+  fn jump(&mut self, target: usize) -> Vec<Delta> {
+    let (mut up, mut down) = (self.current, target);
+    let mut steps = Vec::new();
+    let mut redo = Vec::new();
+    while up != down {
+      if up > down {
+        steps.push(self.nodes[up].delta.inverse());
+        up = self.nodes[up].parent.unwrap_or(0);
+      } else {
+        redo.push(self.nodes[down].delta.clone());
+        let parent = self.nodes[down].parent.unwrap_or(0);
+        self.nodes[parent].redo_child = Some(down);
+        down = parent;
+      }
+    }
+    steps.extend(redo.into_iter().rev());
+    self.current = target;
+    steps
+  }
+}
+
+impl Display for StateHistory {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for (idx, node) in self.nodes.iter().enumerate() {
+      writeln!(
+        f,
+        "{}{} <- {} {} {:?} -> {:?}",
+        if idx == self.current { "*" } else { " " },
+        idx,
+        node.parent.map_or("-".to_string(), |p| p.to_string()),
+        node.delta.from,
+        node.delta.removed,
+        node.delta.inserted,
+      )?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn delta(from: usize, removed: &str, inserted: &str) -> Delta {
+    Delta { from, removed: removed.to_string(), inserted: inserted.to_string() }
+  }
+
+  // Applies steps to a plain string of ASCII chars
+  fn apply(text: &mut String, steps: Option<Vec<Delta>>) {
+    for step in steps.unwrap_or_default() {
+      text.replace_range(step.from..step.from + step.removed.len(), &step.inserted);
+    }
+  }
+
+  #[test]
+  fn undo_and_redo_follow_the_last_branch() {
+    let mut history = StateHistory::new();
+    history.push(delta(0, "a", "x"));
+    history.push(delta(1, "b", "y"));
+    let mut text = "xyc".to_string();
+    apply(&mut text, history.previous());
+    assert_eq!(text, "xbc");
+    // A new change starts a second branch from "xbc"
+    history.push(delta(2, "c", "z"));
+    text = "xbz".to_string();
+    apply(&mut text, history.previous());
+    apply(&mut text, history.previous());
+    assert_eq!(text, "abc");
+    assert!(history.previous().is_none());
+    // Redo goes down the newer branch
+    apply(&mut text, history.next());
+    apply(&mut text, history.next());
+    assert_eq!(text, "xbz");
+    assert!(history.next().is_none());
+  }
+
+  #[test]
+  fn earlier_and_later_walk_through_time() {
+    let mut history = StateHistory::new();
+    let mut text = "xbz".to_string();
+    history.push(delta(0, "a", "x"));
+    history.push(delta(1, "b", "y"));
+    history.previous();
+    history.push(delta(2, "c", "z"));
+    // g- from the second branch goes over to the first one
+    apply(&mut text, history.earlier());
+    assert_eq!(text, "xyc");
+    apply(&mut text, history.earlier());
+    assert_eq!(text, "xbc");
+    apply(&mut text, history.earlier());
+    assert_eq!(text, "abc");
+    assert!(history.earlier().is_none());
+    apply(&mut text, history.later());
+    apply(&mut text, history.later());
+    assert_eq!(text, "xyc");
+    // and redo then follows the branch g+ took
+    apply(&mut text, history.previous());
+    apply(&mut text, history.next());
+    assert_eq!(text, "xyc");
+    apply(&mut text, history.later());
+    assert_eq!(text, "xbz");
+    assert!(history.later().is_none());
+  }
+
+  #[test]
+  fn saved_node() {
+    let mut history = StateHistory::new();
+    assert!(history.is_saved());
+    history.push(delta(0, "a", "x"));
+    assert!(!history.is_saved());
+    history.mark_saved();
+    history.previous();
+    assert!(!history.is_saved());
+    history.next();
+    assert!(history.is_saved());
+    // Changes that change nothing add no step
+    history.push(delta(0, "x", "x"));
+    assert!(history.is_saved());
+  }
+}
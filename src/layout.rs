@@ -0,0 +1,349 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Layout
+//------------------------------------------------------
+//------------------------------------------------------
+use crate::motion::Dir;
+
+// Screen region in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+  pub x: i32,
+  pub y: i32,
+  pub w: u32,
+  pub h: u32,
+}
+
+impl Area {
+  pub fn new(x: i32, y: i32, w: u32, h: u32) -> Self {
+    Area { x, y, w, h }
+  }
+
+  fn right(&self) -> i32 {
+    self.x + self.w as i32
+  }
+
+  fn bottom(&self) -> i32 {
+    self.y + self.h as i32
+  }
+
+  // Shared length of two ranges
+  fn overlap(a: (i32, i32), b: (i32, i32)) -> i32 {
+    a.1.min(b.1) - a.0.max(b.0)
+  }
+}
+
+// Horizontal panes are stacked on top of each other (:split),
+// vertical ones side by side (:vsplit).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+  Horizontal,
+  Vertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scroll {
+  // Ctrl-e / Ctrl-y / mouse wheel, the cursor only moves to stay in view
+  Line(isize),
+  // Ctrl-d / Ctrl-u
+  HalfPage(isize),
+  // Ctrl-f / Ctrl-b
+  Page(isize),
+  // zt / zz / zb
+  Top,
+  Center,
+  Bottom,
+}
+
+//------------------------------------------------------
+// Pane
+//------------------------------------------------------
+// A viewport onto a buffer. `cursor` is only kept up to date while the
+// pane is not focused, the focused pane's cursor lives in its buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pane {
+  pub id: usize,
+  pub buffer: usize,
+  pub cursor: usize,
+  pub top: usize,
+  pub left: usize,
+}
+
+impl Pane {
+  // Moves the view and returns the row the cursor should end up on.
+  // `rows` is the height of the view and `last` the buffer's last row.
+  pub fn scroll(&mut self, scroll: Scroll, row: usize, rows: usize, last: usize, so: usize) -> usize {
+    let rows = rows.max(1);
+    let so = so.min((rows - 1) / 2);
+    let shift = |top: usize, n: isize| (top as isize + n).clamp(0, last as isize) as usize;
+    let half = (rows / 2).max(1) as isize;
+    let page = rows.saturating_sub(2).max(1) as isize;
+    let row = match scroll {
+      Scroll::Line(n) => {
+        self.top = shift(self.top, n);
+        row
+      },
+      Scroll::HalfPage(n) => {
+        self.top = shift(self.top, n * half);
+        shift(row, n * half)
+      },
+      Scroll::Page(n) => {
+        self.top = shift(self.top, n * page);
+        match n > 0 {
+          true  => self.top + so,
+          false => (self.top + rows - 1).saturating_sub(so),
+        }
+      },
+      Scroll::Top => {
+        self.top = row.saturating_sub(so);
+        row
+      },
+      Scroll::Center => {
+        self.top = row.saturating_sub(rows / 2);
+        row
+      },
+      Scroll::Bottom => {
+        self.top = (row + so + 1).saturating_sub(rows);
+        row
+      },
+    };
+    let low = match self.top {
+      0 => 0,
+      top => top + so,
+    };
+    let high = match self.top + rows > last {
+      true  => last,
+      false => self.top + rows - 1 - so,
+    };
+    row.min(high).max(low).min(last)
+  }
+
+  // Moves the view just enough to have `row` inside the margins.
+  // `height` gives the screen lines taken by a row, more than one when
+  // wrapped and none inside a closed fold, and `sub_row` is the screen
+  // line of the cursor inside its row.
+  pub fn follow_wrapped<F>(&mut self, row: usize, sub_row: usize, rows: usize, last: usize, so: usize, height: F)
+  where F: Fn(usize) -> usize {
+    let rows = rows.max(1);
+    let so = so.min((rows - 1) / 2);
+    if row < self.top + so {
+      self.top = row.saturating_sub(so);
+      return
+    }
+    let below = ((row + 1)..=(row + so).min(last)).map(&height).sum::<usize>();
+    let mut used: usize = (self.top..row).map(&height).sum::<usize>() + sub_row + 1 + below;
+    while used > rows && self.top < row {
+      used -= height(self.top);
+      self.top += 1;
+    }
+  }
+
+  // Horizontal scroll keeping `col` in view when rows are not wrapped
+  pub fn follow_col(&mut self, col: usize, cols: usize) {
+    if col < self.left {
+      self.left = col;
+    } else if col >= self.left + cols {
+      self.left = col + 1 - cols;
+    }
+  }
+}
+
+enum Node {
+  Leaf(Pane),
+  Split(Axis, Vec<Node>),
+}
+
+impl Node {
+  fn panes<'a>(&'a self, out: &mut Vec<&'a Pane>) {
+    match self {
+      Node::Leaf(pane) => out.push(pane),
+      Node::Split(_, children) => children.iter().for_each(|c| c.panes(out)),
+    }
+  }
+
+  fn find_mut(&mut self, id: usize) -> Option<&mut Pane> {
+    match self {
+      Node::Leaf(pane) if pane.id == id => Some(pane),
+      Node::Leaf(_) => None,
+      Node::Split(_, children) => children.iter_mut().find_map(|c| c.find_mut(id)),
+    }
+  }
+
+  fn split(&mut self, id: usize, axis: Axis, new: Pane) -> bool {
+    match self {
+      Node::Leaf(pane) => {
+        if pane.id != id {
+          return false
+        }
+        let old = *pane;
+        *self = Node::Split(axis, vec![Node::Leaf(new), Node::Leaf(old)]);
+        true
+      },
+      Node::Split(own, children) => {
+        let pos = children.iter().position(|c| matches!(c, Node::Leaf(p) if p.id == id));
+        match pos {
+          Some(pos) if *own == axis => {
+            children.insert(pos, Node::Leaf(new));
+            true
+          },
+          _ => children.iter_mut().any(|c| c.split(id, axis, new)),
+        }
+      },
+    }
+  }
+
+  // Removes the pane and returns the id of a pane next to it
+  fn remove(&mut self, id: usize) -> Option<usize> {
+    let Node::Split(_, children) = self else { return None };
+    let found = match children.iter().position(|c| matches!(c, Node::Leaf(p) if p.id == id)) {
+      Some(pos) => {
+        children.remove(pos);
+        Some(children[pos.min(children.len() - 1)].first().id)
+      },
+      None => children.iter_mut().find_map(|c| c.remove(id)),
+    };
+    if children.len() == 1 {
+      let only = children.remove(0);
+      *self = only;
+    }
+    found
+  }
+
+  fn first(&self) -> &Pane {
+    match self {
+      Node::Leaf(pane) => pane,
+      Node::Split(_, children) => children[0].first(),
+    }
+  }
+
+  fn arrange(&self, area: Area, out: &mut Vec<(usize, Area)>) {
+    match self {
+      Node::Leaf(pane) => out.push((pane.id, area)),
+      Node::Split(axis, children) => {
+        let n = children.len() as u32;
+        for (i, child) in children.iter().enumerate() {
+          let i = i as u32;
+          let part = match axis {
+            Axis::Horizontal => {
+              let (from, to) = (area.h * i / n, area.h * (i + 1) / n);
+              Area::new(area.x, area.y + from as i32, area.w, to - from)
+            },
+            Axis::Vertical => {
+              let (from, to) = (area.w * i / n, area.w * (i + 1) / n);
+              Area::new(area.x + from as i32, area.y, to - from, area.h)
+            },
+          };
+          child.arrange(part, out);
+        }
+      }
+    }
+  }
+}
+
+//------------------------------------------------------
+// Layout
+//------------------------------------------------------
+pub struct Layout {
+  root: Node,
+  focus: usize,
+  next_id: usize,
+}
+
+impl Layout {
+  pub fn new(buffer: usize) -> Self {
+    Layout {
+      root: Node::Leaf(Pane { id: 1, buffer, cursor: 0, top: 0, left: 0 }),
+      focus: 1,
+      next_id: 2,
+    }
+  }
+
+  pub fn focused(&self) -> &Pane {
+    self.panes()
+      .into_iter()
+      .find(|pane| pane.id == self.focus)
+      .expect("Focused pane is always in the layout")
+  }
+
+  pub fn focused_mut(&mut self) -> &mut Pane {
+    self.root
+      .find_mut(self.focus)
+      .expect("Focused pane is always in the layout")
+  }
+
+  pub fn get_mut(&mut self, id: usize) -> Option<&mut Pane> {
+    self.root.find_mut(id)
+  }
+
+  pub fn panes(&self) -> Vec<&Pane> {
+    let mut out = Vec::new();
+    self.root.panes(&mut out);
+    out
+  }
+
+  pub fn ids(&self) -> Vec<usize> {
+    self.panes().iter().map(|pane| pane.id).collect()
+  }
+
+  pub fn count(&self) -> usize {
+    self.panes().len()
+  }
+
+  pub fn focus(&mut self, id: usize) {
+    if self.root.find_mut(id).is_some() {
+      self.focus = id
+    }
+  }
+
+  // Splits the focused pane, the new pane shows the same buffer and
+  // takes focus.
+  pub fn split(&mut self, axis: Axis) -> usize {
+    let id = self.next_id;
+    self.next_id += 1;
+    let new = Pane { id, ..*self.focused() };
+    self.root.split(self.focus, axis, new);
+    self.focus = id;
+    id
+  }
+
+  pub fn close(&mut self) -> Result<(), String> {
+    if self.count() == 1 {
+      return Err("Cannot close last window".to_string())
+    }
+    if let Some(next) = self.root.remove(self.focus) {
+      self.focus = next
+    }
+    Ok(())
+  }
+
+  pub fn only(&mut self) {
+    self.root = Node::Leaf(*self.focused());
+  }
+
+  pub fn arrange(&self, area: Area) -> Vec<(usize, Area)> {
+    let mut out = Vec::new();
+    self.root.arrange(area, &mut out);
+    out
+  }
+
+  // Closest pane in the given direction from the focused one,
+  // preferring the one sharing the most edge with it.
+  pub fn neighbour(&self, area: Area, dir: Dir) -> Option<usize> {
+    let areas = self.arrange(area);
+    let (_, from) = *areas.iter().find(|(id, _)| *id == self.focus)?;
+    areas
+      .iter()
+      .filter(|(id, _)| *id != self.focus)
+      .filter_map(|(id, to)| {
+        let (distance, overlap) = match dir {
+          Dir::L => (from.x - to.right(), Area::overlap((from.y, from.bottom()), (to.y, to.bottom()))),
+          Dir::R => (to.x - from.right(), Area::overlap((from.y, from.bottom()), (to.y, to.bottom()))),
+          Dir::U => (from.y - to.bottom(), Area::overlap((from.x, from.right()), (to.x, to.right()))),
+          Dir::D => (to.y - from.bottom(), Area::overlap((from.x, from.right()), (to.x, to.right()))),
+        };
+        (distance >= 0 && overlap > 0).then_some((distance, -overlap, *id))
+      })
+      .min()
+      .map(|(_, _, id)| id)
+  }
+}
@@ -1,23 +1,11 @@
 use std::fmt::Display;
 
 
-macro_rules! motion {
-  ($cmd1:expr, $cmd2:expr, $cmd3:expr) => {
-    Motion {
-      buf: [$cmd1, $cmd2, $cmd3],
-    }
-  };
-}
-
-pub const MOVE_L:   Motion = motion!(Cmd::Verb(Action::Move), Cmd::By(1), Cmd::ToDir(Dir::L));
-pub const MOVE_R:   Motion = motion!(Cmd::Verb(Action::Move), Cmd::By(1), Cmd::ToDir(Dir::R));
-pub const MOVE_U:   Motion = motion!(Cmd::Verb(Action::Move), Cmd::By(1), Cmd::ToDir(Dir::U));
-pub const MOVE_D:   Motion = motion!(Cmd::Verb(Action::Move), Cmd::By(1), Cmd::ToDir(Dir::D));
-pub const CUTBACK:  Motion = motion!(Cmd::Verb(Action::Cut),  Cmd::By(1), Cmd::ToDir(Dir::L));
-pub const _NEXTLINE: Motion = motion!(Cmd::Verb(Action::Move), Cmd::By(1), Cmd::ToDir(Dir::D));
-
-pub const MOVE_END: Motion       = motion!(Cmd::Verb(Action::Move), Cmd::ToDest(Dest::Endl),     Cmd::None);
-pub const _MOVE_TXT_START: Motion = motion!(Cmd::Verb(Action::Move), Cmd::ToDest(Dest::TxtStart), Cmd::None);
+pub const MOVE_L:   Motion = Motion::new(Action::Move, Target::Dir(Dir::L));
+pub const MOVE_R:   Motion = Motion::new(Action::Move, Target::Dir(Dir::R));
+pub const MOVE_U:   Motion = Motion::new(Action::Move, Target::Dir(Dir::U));
+pub const MOVE_D:   Motion = Motion::new(Action::Move, Target::Dir(Dir::D));
+pub const CUTBACK:  Motion = Motion::new(Action::Cut,  Target::Dir(Dir::L));
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Dir {
@@ -30,152 +18,422 @@ pub enum Dir {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Dest {
   Line,
+  LineStart,
+  // _ goes to the text of a later row and is linewise
   TxtStart,
+  // ^ goes to the text of the cursor's row and is charwise
+  FirstNonBlank,
   Endl,
+  // 1-based, gg and <n>G
+  Row(usize),
+  LastRow,
+  // Target and direction, true is forwards
+  Seek(Seek, bool),
+  // % to the bracket or syntax node end paired with the cursor's
+  Partner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seek {
+  // f F
+  Char(char),
+  // t T, true when repeated with ; or , so a match right next to the
+  // cursor is skipped
+  Till(char, bool),
+  // w b / W B
+  Word,
+  BigWord,
+  // e ge / E gE
+  WordEnd,
+  BigWordEnd,
+  // ( )
+  Sentence,
+  // { }
+  Paragraph,
+}
+
+// Text objects, typed after i (inner) or a (around) following an
+// operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Object {
+  Word,
+  BigWord,
+  Sentence,
+  Paragraph,
+  // " ' or `
+  Quote(char),
+  // Opening and closing bracket
+  Bracket(char, char),
+  // XML/HTML tag
+  Tag,
+  // Syntax nodes, found with the tree-sitter feature only
+  Function,
+  Class,
+  Argument,
+  Block,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Action {
   Move,
   Cut,
+  Change,
+  Yank,
+  Join,
+  // ~ u U and r{c} in Visual mode
+  SwapCase,
+  Lowercase,
+  Uppercase,
+  Replace(char),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Cmd {
-  Verb(Action),
-  By(usize),
-  ToDest(Dest),
-  ToDir(Dir),
-  None
+impl Action {
+  // Whether the action changes buffer content
+  pub fn is_disruptive(&self) -> bool {
+    !matches!(self, Action::Move | Action::Yank)
+  }
 }
 
-impl Cmd {
-  pub fn unwrap_dir(&self) -> Dir {
-    match self {
-      Cmd::ToDir(d) => *d,
-      _ => panic!("Called unwrap_dir() on {:?}", self)
-    }
-  }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Visual {
+  Char,
+  Line,
+  Block,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+  Dir(Dir),
+  Dest(Dest),
+  // true for inner objects
+  Object(Object, bool),
+  // The buffer's visual selection
+  Selection(Visual),
+}
+
+//------------------------------------------------------
+// Motion
+//------------------------------------------------------
+// A complete command: ["x][count]{operator}[count]{motion}.
+// Counts typed on both sides of the operator are multiplied.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Motion {
-  pub buf: [Cmd; 3],
+  pub register: Option<char>,
+  pub count: Option<usize>,
+  pub verb: Action,
+  pub target: Target,
 }
 
 impl Motion {
-  pub fn new() -> Self {
-    Motion { buf: [Cmd::Verb(Action::Move), Cmd::None, Cmd::None] }
+  pub const fn new(verb: Action, target: Target) -> Self {
+    Motion { register: None, count: None, verb, target }
   }
-  pub fn from(buf: [Cmd; 3]) -> Self {
-    Motion {buf}
+
+  pub fn count(&self) -> usize {
+    self.count.unwrap_or(1)
   }
+
   // Check if Motion mutates buffer content
   pub fn is_disruptive(&self) -> bool {
-    match self.buf[0] {
-      Cmd::Verb(Action::Cut) => true,
-      Cmd::Verb(Action::Move) => false,
-      _ => panic!("Motion.buff[0] should always be a verb")
+    self.verb.is_disruptive()
+  }
+}
+
+impl Display for Motion {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if let Some(register) = self.register {
+      write!(f, "\"{} ", register)?;
+    }
+    if let Some(count) = self.count {
+      write!(f, "{} ", count)?;
     }
+    write!(f, "{:?} {:?}", self.verb, self.target)
+  }
+}
+
+//------------------------------------------------------
+// Pending
+//------------------------------------------------------
+// Operator-pending state, collects keys typed in Edit mode until they
+// form a Motion.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Pending {
+  register: Option<char>,
+  // '"' was typed, the next key names a register
+  naming: bool,
+  count: Option<usize>,
+  operator: Option<Action>,
+  // Count typed after the operator
+  motion_count: Option<usize>,
+  // i or a was typed after the operator, true for i
+  object: Option<bool>,
+  // f F t or T waiting for its char
+  find: Option<char>,
+  // Kept over resets for ; and ,
+  last_find: Option<(Seek, bool)>,
+}
+
+impl Pending {
+  pub fn new() -> Self {
+    Pending::default()
   }
 
-  // returned motion should always be [Verb, By, Dir] or [Verb, Dest, None]
   pub fn push(&mut self, c: Option<char>) -> Option<Motion> {
     use Action::*;
-    use Cmd::*;
-    if self.buf[0] == Cmd::None { panic!("Motion has no verb") }
-    if c.is_none() {
-      return Option::None
+    let c = c?;
+    if self.naming {
+      self.naming = false;
+      self.register = Some(c);
+      return None
     }
-    let c = c.unwrap();
-    let mut ready = false;
-
-    if c.is_alphabetic(){ match c {
-      'x' => {
-        self.buf[0] = Verb(Cut);
-        if self.buf[1] == None { self.buf[1] = Cmd::By(1) }
-        self.buf[2] = ToDir(Dir::R);
-        ready = true
-      }
-      'l' => {
-        if self.buf[1] == None {self.buf[1] = By(1)}
-        self.buf[2] = ToDir(Dir::R);
-        ready = true
-      }
-      'h' => {
-        if self.buf[1] == None {self.buf[1] = By(1)}
-        self.buf[2] = ToDir(Dir::L);
-        ready = true
-      }
-      'k' => {
-        if self.buf[1] == None {self.buf[1] = By(1)}
-        self.buf[2] = ToDir(Dir::U);
-        ready = true
-      }
-      'j' => {
-        if self.buf[1] == None {self.buf[1] = By(1)}
-        self.buf[2] = ToDir(Dir::D);
-        ready = true
-      }
-      'd' => {
-        if self.buf[0] == Verb(Cut) {
-          match self.buf[1] {
-            Cmd::None => self.buf[1] = ToDest(Dest::Line),
-            Cmd::By(_) => self.buf[2] = ToDir(Dir::D),
-            _ => panic!("Motion[1] != None | By(n) ")
-          }
-          ready = true;
-        }
-        self.buf[0] = Verb(Cut)
-      }
-      _ => {}
-      }
+    if let Some(inner) = self.object.take() {
+      let object = match c {
+        'w' => Object::Word,
+        'W' => Object::BigWord,
+        's' => Object::Sentence,
+        'p' => Object::Paragraph,
+        '"' | '\'' | '`' => Object::Quote(c),
+        '(' | ')' | 'b' => Object::Bracket('(', ')'),
+        '{' | '}' | 'B' => Object::Bracket('{', '}'),
+        '[' | ']' => Object::Bracket('[', ']'),
+        '<' | '>' => Object::Bracket('<', '>'),
+        't' => Object::Tag,
+        'f' => Object::Function,
+        'c' => Object::Class,
+        'a' => Object::Argument,
+        'o' => Object::Block,
+        _ => return self.cancel(),
+      };
+      return self.finish(Target::Object(object, inner))
     }
-
-    if c.is_ascii_punctuation() {
-      ready = true;
-      match c {
-        '_' => {
-          self.buf[1] = Cmd::ToDest(Dest::TxtStart);
-        },
-        _   => ready = false
-      }
+    if let Some(key) = self.find.take() {
+      let seek = match key {
+        'f' | 'F' => Seek::Char(c),
+        _ => Seek::Till(c, false),
+      };
+      let forwards = key == 'f' || key == 't';
+      self.last_find = Some((seek, forwards));
+      return self.finish(Target::Dest(Dest::Seek(seek, forwards)))
     }
+    let dest = match c {
+      'l' => return self.finish(Target::Dir(Dir::R)),
+      'h' => return self.finish(Target::Dir(Dir::L)),
+      'k' => return self.finish(Target::Dir(Dir::U)),
+      'j' => return self.finish(Target::Dir(Dir::D)),
+      'G' => match self.take_count() {
+        Some(n) => Dest::Row(n),
+        None => Dest::LastRow,
+      },
+      '_' => Dest::TxtStart,
+      '^' => Dest::FirstNonBlank,
+      '$' => Dest::Endl,
+      'w' => Dest::Seek(Seek::Word, true),
+      'b' => Dest::Seek(Seek::Word, false),
+      'W' => Dest::Seek(Seek::BigWord, true),
+      'B' => Dest::Seek(Seek::BigWord, false),
+      'e' => Dest::Seek(Seek::WordEnd, true),
+      'E' => Dest::Seek(Seek::BigWordEnd, true),
+      '(' => Dest::Seek(Seek::Sentence, false),
+      ')' => Dest::Seek(Seek::Sentence, true),
+      '{' => Dest::Seek(Seek::Paragraph, false),
+      '}' => Dest::Seek(Seek::Paragraph, true),
+      '%' => Dest::Partner,
+      'f' | 'F' | 't' | 'T' => {
+        self.find = Some(c);
+        return None
+      },
+      ';' | ',' => match self.last_find {
+        Some((seek, forwards)) => {
+          let seek = match seek {
+            Seek::Till(c, _) => Seek::Till(c, true),
+            _ => seek,
+          };
+          Dest::Seek(seek, forwards == (c == ';'))
+        },
+        None => return self.cancel(),
+      },
+      '0' if self.counting().is_none() => Dest::LineStart,
+      '1'..='9' | '0' => {
+        let digit = c as usize - '0' as usize;
+        let count = self.counting().unwrap_or(0).saturating_mul(10).saturating_add(digit);
+        match self.operator {
+          Some(_) => self.motion_count = Some(count),
+          None => self.count = Some(count),
+        }
+        return None
+      },
+      'i' | 'a' if self.operator.is_some() => {
+        self.object = Some(c == 'i');
+        return None
+      },
+      '"' if self.operator.is_none() => {
+        self.naming = true;
+        return None
+      },
+      'd' | 'c' | 'y' => {
+        let verb = match c {
+          'd' => Cut,
+          'c' => Change,
+          _   => Yank,
+        };
+        match self.operator {
+          // Doubled operator works on whole lines
+          Some(operator) if operator == verb => Dest::Line,
+          Some(_) => return self.cancel(),
+          None => {
+            self.operator = Some(verb);
+            return None
+          },
+        }
+      },
+      // Shorthands for an operator and its motion
+      'x' | 's' | 'D' | 'C' | 'S' | 'Y' | 'J' if self.operator.is_none() => {
+        let (verb, target) = match c {
+          'x' => (Cut, Target::Dir(Dir::R)),
+          's' => (Change, Target::Dir(Dir::R)),
+          'D' => (Cut, Target::Dest(Dest::Endl)),
+          'C' => (Change, Target::Dest(Dest::Endl)),
+          'S' => (Change, Target::Dest(Dest::Line)),
+          'Y' => (Yank, Target::Dest(Dest::Line)),
+          _   => (Join, Target::Dest(Dest::Line)),
+        };
+        self.operator = Some(verb);
+        return self.finish(target)
+      },
+      _ => return self.cancel(),
+    };
+    self.finish(Target::Dest(dest))
+  }
+
+  // Completes the command with a target, also used for the ones reached
+  // through a prefix like ge
+  pub fn finish(&mut self, target: Target) -> Option<Motion> {
+    let motion = Motion {
+      register: self.register,
+      count: self.take_count(),
+      verb: self.operator.unwrap_or(Action::Move),
+      target,
+    };
+    self.reset();
+    Some(motion)
+  }
+
+  // An operator typed in Visual mode works on the selection right away
+  pub fn operate(&mut self, verb: Action, visual: Visual) -> Motion {
+    let motion = Motion {
+      register: self.register,
+      count: self.take_count(),
+      verb,
+      target: Target::Selection(visual),
+    };
+    self.reset();
+    motion
+  }
 
-    if c.is_ascii_digit() {
-      if let By(n) = self.buf[1] {
-        self.buf[1] = By(n.saturating_mul(10).saturating_add((c as usize) - 48));
-      } else if c as u8 != 48 {
-        self.buf[1] = By((c as u8 - 48) as usize)
-      } else {
-        self.buf[1] = ToDest(Dest::Endl);
-        ready = true;
-      }
+  // Both counts multiplied, taking them out of the pending state
+  pub fn take_count(&mut self) -> Option<usize> {
+    match (self.count.take(), self.motion_count.take()) {
+      (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+      (a, b) => a.or(b),
     }
+  }
 
-    match ready {
-      true  => {
-        let command = Some(*self);
-        self.reset();
-        command
-      },
-      false => Option::None
+  pub fn register(&self) -> Option<char> {
+    self.register
+  }
+
+  pub fn operator(&self) -> Option<Action> {
+    self.operator
+  }
+
+  // i or a in Visual mode, the next key names the text object
+  pub fn select_object(&mut self, inner: bool) {
+    self.object = Some(inner);
+  }
+
+  // The next key is a char or register name, not a command
+  pub fn is_awaiting(&self) -> bool {
+    self.naming || self.object.is_some() || self.find.is_some()
+  }
+
+  pub fn reset(&mut self) {
+    *self = Pending { last_find: self.last_find, ..Pending::default() };
+  }
+
+  fn counting(&self) -> Option<usize> {
+    match self.operator {
+      Some(_) => self.motion_count,
+      None => self.count,
     }
   }
 
-  fn reset(&mut self) {
-    self.buf = [Cmd::Verb(Action::Move), Cmd::None, Cmd::None];
+  fn cancel(&mut self) -> Option<Motion> {
+    self.reset();
+    None
   }
 }
 
-impl Display for Motion {
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{:?} {}{:?}", 
-    self.buf[0], 
-    match self.buf[1] {
-      Cmd::None => "".to_string(),
-      _ => format!("{:?} ", self.buf[1])
-    },
-    self.buf[2])
-  }
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // The motion the keys complete, None while they are still pending
+  fn parse(keys: &str) -> Option<Motion> {
+    let mut pending = Pending::new();
+    keys.chars().fold(None, |_, c| pending.push(Some(c)))
+  }
+
+  #[test]
+  fn counts_on_both_sides_multiply() {
+    let motion = parse("2d3w").unwrap();
+    assert_eq!(motion.count, Some(6));
+    assert_eq!(motion.verb, Action::Cut);
+    assert_eq!(motion.target, Target::Dest(Dest::Seek(Seek::Word, true)));
+    assert_eq!(parse("10j").unwrap().count, Some(10));
+    assert_eq!(parse("c$").unwrap(), Motion::new(Action::Change, Target::Dest(Dest::Endl)));
+  }
+
+  #[test]
+  fn registers_and_objects() {
+    let motion = parse("\"ayiw").unwrap();
+    assert_eq!(motion.register, Some('a'));
+    assert_eq!(motion.verb, Action::Yank);
+    assert_eq!(motion.target, Target::Object(Object::Word, true));
+    assert_eq!(parse("da(").unwrap().target, Target::Object(Object::Bracket('(', ')'), false));
+    assert_eq!(parse("\"a").map(|motion| motion.register), None);
+  }
+
+  #[test]
+  fn doubled_operators_and_shorthands() {
+    assert_eq!(parse("3dd").unwrap(), Motion { count: Some(3), ..Motion::new(Action::Cut, Target::Dest(Dest::Line)) });
+    assert_eq!(parse("yy").unwrap().target, Target::Dest(Dest::Line));
+    assert_eq!(parse("x").unwrap(), Motion::new(Action::Cut, Target::Dir(Dir::R)));
+    assert_eq!(parse("D").unwrap(), Motion::new(Action::Cut, Target::Dest(Dest::Endl)));
+    // 0 is a motion unless it continues a count
+    assert_eq!(parse("0").unwrap().target, Target::Dest(Dest::LineStart));
+    assert_eq!(parse("10l").unwrap().count, Some(10));
+    // Mismatched operators cancel the command
+    assert_eq!(parse("dy"), None);
+    assert_eq!(parse("dyw").unwrap(), Motion::new(Action::Move, Target::Dest(Dest::Seek(Seek::Word, true))));
+  }
+
+  #[test]
+  fn finds_repeat_with_semicolon_and_comma() {
+    let mut pending = Pending::new();
+    let mut push = |keys: &str| keys.chars().fold(None, |_, c| pending.push(Some(c))).unwrap().target;
+    assert_eq!(push("tx"), Target::Dest(Dest::Seek(Seek::Till('x', false), true)));
+    assert_eq!(push(";"), Target::Dest(Dest::Seek(Seek::Till('x', true), true)));
+    assert_eq!(push(","), Target::Dest(Dest::Seek(Seek::Till('x', true), false)));
+    assert_eq!(push("Fy"), Target::Dest(Dest::Seek(Seek::Char('y'), false)));
+    assert_eq!(push(";"), Target::Dest(Dest::Seek(Seek::Char('y'), false)));
+    assert_eq!(push(","), Target::Dest(Dest::Seek(Seek::Char('y'), true)));
+  }
+
+  #[test]
+  fn disruptive_verbs() {
+    assert!(!MOVE_L.is_disruptive());
+    assert!(CUTBACK.is_disruptive());
+    assert!(!parse("yw").unwrap().is_disruptive());
+    assert!(Motion::new(Action::SwapCase, Target::Dir(Dir::R)).is_disruptive());
+  }
+}
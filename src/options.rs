@@ -0,0 +1,92 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Options
+//------------------------------------------------------
+//------------------------------------------------------
+use crate::search::Syntax;
+
+// Editor settings changed with `:set`
+pub struct Options {
+  pub scrolloff: usize,
+  pub wrap: bool,
+  // Columns > and < shift by
+  pub shiftwidth: usize,
+  // Register used instead of the unnamed one, '+' for unnamedplus and
+  // '*' for unnamed
+  pub clipboard: Option<char>,
+  // Syntax of search and :s patterns, vim or rust
+  pub regex: Syntax,
+}
+
+impl Default for Options {
+  fn default() -> Self {
+    Options {
+      scrolloff: 5,
+      wrap: true,
+      shiftwidth: 2,
+      clipboard: None,
+      regex: Syntax::Vim,
+    }
+  }
+}
+
+impl Options {
+  // Handles one `:set` argument: `name=value`, `name?` to show a value.
+  // Returns the text to show in the status bar.
+  pub fn set(&mut self, arg: &str) -> Result<String, String> {
+    if let Some(name) = arg.strip_suffix('?') {
+      return self.get(name)
+    }
+    let (name, value) = match arg.split_once('=') {
+      Some((name, value)) => (name, Some(value)),
+      None => (arg, None),
+    };
+    match (name, value) {
+      ("scrolloff" | "so", Some(value)) => self.scrolloff = parse_number(name, value)?,
+      ("scrolloff" | "so", None) => return self.get(name),
+      ("shiftwidth" | "sw", Some(value)) => self.shiftwidth = parse_number(name, value)?,
+      ("shiftwidth" | "sw", None) => return self.get(name),
+      ("clipboard" | "cb", Some(value)) => self.clipboard = match value {
+        "unnamedplus" => Some('+'),
+        "unnamed" => Some('*'),
+        "" => None,
+        _ => return Err(format!("Invalid argument: {}", arg)),
+      },
+      ("clipboard" | "cb", None) => return self.get(name),
+      ("regex" | "rx", Some(value)) => self.regex = match value {
+        "vim" => Syntax::Vim,
+        "rust" => Syntax::Rust,
+        _ => return Err(format!("Invalid argument: {}", arg)),
+      },
+      ("regex" | "rx", None) => return self.get(name),
+      ("wrap", None) => self.wrap = true,
+      ("nowrap", None) => self.wrap = false,
+      _ => return Err(format!("Unknown option: {}", arg)),
+    }
+    Ok(String::new())
+  }
+
+  fn get(&self, name: &str) -> Result<String, String> {
+    match name {
+      "scrolloff" | "so" => Ok(format!("scrolloff={}", self.scrolloff)),
+      "shiftwidth" | "sw" => Ok(format!("shiftwidth={}", self.shiftwidth)),
+      "clipboard" | "cb" => Ok(format!("clipboard={}", match self.clipboard {
+        Some('+') => "unnamedplus",
+        Some(_) => "unnamed",
+        None => "",
+      })),
+      "regex" | "rx" => Ok(format!("regex={}", match self.regex {
+        Syntax::Vim => "vim",
+        Syntax::Rust => "rust",
+      })),
+      "wrap" => Ok(format!("{}wrap", if self.wrap { "" } else { "no" })),
+      _ => Err(format!("Unknown option: {}", name)),
+    }
+  }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+  value
+    .parse()
+    .map_err(|_| format!("Number required after =: {}={}", name, value))
+}
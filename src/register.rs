@@ -0,0 +1,231 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Registers
+//------------------------------------------------------
+//------------------------------------------------------
+use crate::buffer::buffer::{Yanked, YankKind};
+
+// Vim's registers. Deletes of whole rows or several rows shift through
+// "1 to "9, smaller ones go to "-, yanks to "0. The unnamed register
+// holds whatever was written last.
+#[derive(Default)]
+pub struct Registers {
+  unnamed: Option<Yanked>,
+  numbered: [Option<Yanked>; 10],
+  named: [Option<Yanked>; 26],
+  small: Option<Yanked>,
+  // Last text sent to the system clipboard through "+ or "*, pasting the
+  // same text back keeps its kind
+  clipboard: Option<Yanked>,
+  // Text typed in the Insert mode going on, and in the last one
+  typing: String,
+  last_insert: String,
+  last_command: String,
+}
+
+impl Registers {
+  pub fn new() -> Self {
+    Registers::default()
+  }
+
+  // Stores text an operator took, `delete` is false for yanks
+  pub fn store(&mut self, register: Option<char>, yanked: Yanked, delete: bool) -> Result<(), String> {
+    match register {
+      Some('_') => return Ok(()),
+      Some(c @ 'a'..='z') => self.named[c as usize - 'a' as usize] = Some(yanked.clone()),
+      Some(c @ 'A'..='Z') => {
+        let slot = &mut self.named[c.to_ascii_lowercase() as usize - 'a' as usize];
+        let appended = match slot.take() {
+          Some(old) => old.append(&yanked),
+          None => yanked,
+        };
+        *slot = Some(appended.clone());
+        self.unnamed = Some(appended);
+        return Ok(())
+      },
+      Some('+' | '*') => self.clipboard = Some(yanked.clone()),
+      Some('.' | ':' | '%') => return Err("Cannot write to a read-only register".to_string()),
+      Some('"') | None if !delete => self.numbered[0] = Some(yanked.clone()),
+      Some('"') | None if yanked.kind == YankKind::Line || yanked.text.contains('\n') => {
+        self.numbered[1..].rotate_right(1);
+        self.numbered[1] = Some(yanked.clone());
+      },
+      Some('"') | None => self.small = Some(yanked.clone()),
+      Some(c @ '0'..='9') => self.numbered[c as usize - '0' as usize] = Some(yanked.clone()),
+      Some('-') => self.small = Some(yanked.clone()),
+      Some(c) => return Err(format!("Invalid register name: {}", c)),
+    }
+    self.unnamed = Some(yanked);
+    Ok(())
+  }
+
+  // q records macros into registers as charwise text, leaving the
+  // unnamed register alone
+  pub fn record(&mut self, register: char, keys: &str) -> Result<(), String> {
+    let unnamed = self.unnamed.take();
+    let result = self.store(Some(register), Yanked { text: keys.to_string(), kind: YankKind::Char }, false);
+    if register != '"' {
+      self.unnamed = unnamed;
+    }
+    result
+  }
+
+  // `file` is the name "% gives
+  pub fn get(&self, register: Option<char>, file: &str) -> Option<Yanked> {
+    let text = |text: &str| match text.is_empty() {
+      true  => None,
+      false => Some(Yanked { text: text.to_string(), kind: YankKind::Char }),
+    };
+    match register {
+      None | Some('"') => self.unnamed.clone(),
+      Some(c @ '0'..='9') => self.numbered[c as usize - '0' as usize].clone(),
+      Some(c @ ('a'..='z' | 'A'..='Z')) => self.named[c.to_ascii_lowercase() as usize - 'a' as usize].clone(),
+      Some('-') => self.small.clone(),
+      Some('+' | '*') => self.clipboard.clone(),
+      Some('.') => text(&self.last_insert),
+      Some(':') => text(&self.last_command),
+      Some('%') => text(file),
+      _ => None,
+    }
+  }
+
+  // Register holding `text` read from the system clipboard. Text that
+  // did not come from the editor is linewise when it ends in a line break.
+  pub fn clipboard(&self, text: &str) -> Yanked {
+    let text = text.replace("\r\n", "\n");
+    match &self.clipboard {
+      Some(yanked) if yanked.text == text => yanked.clone(),
+      _ => Yanked {
+        kind: if text.ends_with('\n') { YankKind::Line } else { YankKind::Char },
+        text,
+      },
+    }
+  }
+
+  // Insert mode feeds what it types here for ".
+  pub fn typed(&mut self, text: &str) {
+    self.typing.push_str(text);
+  }
+
+  pub fn backspace(&mut self) {
+    self.typing.pop();
+  }
+
+  // Returns the text typed in the Insert mode that ended
+  pub fn finish_insert(&mut self) -> String {
+    let typed = std::mem::take(&mut self.typing);
+    if !typed.is_empty() {
+      self.last_insert = typed.clone();
+    }
+    typed
+  }
+
+  pub fn set_last_command(&mut self, cmd: &str) {
+    self.last_command = cmd.to_string();
+  }
+
+  // :registers, optionally only the ones named in `names`
+  pub fn list(&self, names: Option<&str>, file: &str) -> String {
+    let mut lines = vec!["Type Name Content".to_string()];
+    let all = "\"0123456789abcdefghijklmnopqrstuvwxyz-.:%";
+    for name in all.chars().filter(|c| names.is_none_or(|names| names.contains(*c))) {
+      let Some(yanked) = self.get(Some(name), file) else { continue };
+      let kind = match yanked.kind {
+        YankKind::Char => 'c',
+        YankKind::Line => 'l',
+        YankKind::Block => 'b',
+      };
+      // Line breaks and keys like Esc in macros show as ^J and ^[
+      let content: String = yanked.text
+        .chars()
+        .flat_map(|c| match c.is_ascii_control() {
+          true  => vec!['^', (c as u8 ^ 0x40) as char],
+          false => vec![c],
+        })
+        .take(60)
+        .collect();
+      lines.push(format!("  {}  \"{}   {}", kind, name, content));
+    }
+    lines.join("\n")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn chars(text: &str) -> Yanked {
+    Yanked { text: text.to_string(), kind: YankKind::Char }
+  }
+
+  fn lines(text: &str) -> Yanked {
+    Yanked { text: text.to_string(), kind: YankKind::Line }
+  }
+
+  fn text(registers: &Registers, name: char) -> Option<String> {
+    registers.get(Some(name), "file.rs").map(|yanked| yanked.text)
+  }
+
+  #[test]
+  fn deletes_shift_through_the_numbered_registers() {
+    let mut registers = Registers::new();
+    registers.store(None, chars("yank"), false).unwrap();
+    registers.store(None, lines("one\n"), true).unwrap();
+    registers.store(None, lines("two\n"), true).unwrap();
+    registers.store(None, chars("x"), true).unwrap();
+    assert_eq!(text(&registers, '0').as_deref(), Some("yank"));
+    assert_eq!(text(&registers, '1').as_deref(), Some("two\n"));
+    assert_eq!(text(&registers, '2').as_deref(), Some("one\n"));
+    // Small deletes go to "- and leave the numbered ones alone
+    assert_eq!(text(&registers, '-').as_deref(), Some("x"));
+    assert_eq!(text(&registers, '"').as_deref(), Some("x"));
+    assert_eq!(text(&registers, '3'), None);
+  }
+
+  #[test]
+  fn named_registers_append_in_capitals() {
+    let mut registers = Registers::new();
+    registers.store(Some('a'), chars("foo"), false).unwrap();
+    registers.store(Some('A'), chars("bar"), false).unwrap();
+    assert_eq!(text(&registers, 'a').as_deref(), Some("foobar"));
+    assert_eq!(text(&registers, '"').as_deref(), Some("foobar"));
+    // A named yank doesn't touch "0
+    assert_eq!(text(&registers, '0'), None);
+    registers.store(Some('_'), chars("gone"), true).unwrap();
+    assert_eq!(text(&registers, '"').as_deref(), Some("foobar"));
+  }
+
+  #[test]
+  fn read_only_registers() {
+    let mut registers = Registers::new();
+    assert!(registers.store(Some('.'), chars("x"), false).is_err());
+    assert!(registers.store(Some('!'), chars("x"), false).is_err());
+    registers.typed("abc");
+    registers.backspace();
+    assert_eq!(registers.finish_insert(), "ab");
+    registers.set_last_command("w");
+    assert_eq!(text(&registers, '.').as_deref(), Some("ab"));
+    assert_eq!(text(&registers, ':').as_deref(), Some("w"));
+    assert_eq!(text(&registers, '%').as_deref(), Some("file.rs"));
+  }
+
+  #[test]
+  fn clipboard_text_keeps_its_kind() {
+    let mut registers = Registers::new();
+    assert_eq!(registers.clipboard("a\r\nb\r\n"), lines("a\nb\n"));
+    assert_eq!(registers.clipboard("a\nb"), chars("a\nb"));
+    // Text the editor put there comes back as it went out
+    registers.store(Some('+'), Yanked { text: "ab\ncd".to_string(), kind: YankKind::Block }, false).unwrap();
+    assert_eq!(registers.clipboard("ab\ncd").kind, YankKind::Block);
+    assert_eq!(registers.clipboard("other").kind, YankKind::Char);
+  }
+
+  #[test]
+  fn recording_keeps_the_unnamed_register() {
+    let mut registers = Registers::new();
+    registers.store(None, chars("keep"), false).unwrap();
+    registers.record('q', "dd").unwrap();
+    assert_eq!(text(&registers, 'q').as_deref(), Some("dd"));
+    assert_eq!(text(&registers, '"').as_deref(), Some("keep"));
+  }
+}
@@ -0,0 +1,704 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Renderer
+//------------------------------------------------------
+//------------------------------------------------------
+use std::collections::HashMap;
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::ttf::{Font, FontStyle, Sdl2TtfContext};
+use sdl2::video::{Window, WindowContext};
+
+use crate::buffer::buffer::Buffer;
+use crate::buffer::list::BufferList;
+use crate::buffer::syntax::{Class, Span};
+use crate::editor::Editor;
+use crate::handler::Mode;
+use crate::motion::Visual;
+use crate::layout::{Area, Pane};
+use crate::search::Pattern;
+use crate::theme::Theme;
+
+const FONTSIZE: u16 = 24;
+const STATUS_FONTSIZE: u16 = 16;
+// Height of the status bar and of each message line above it
+const STATUS_LINE: u32 = 20;
+// Side of a glyph atlas page
+const PAGE_SIZE: u32 = 1024;
+
+macro_rules! rect(
+  ($x:expr, $y:expr, $w:expr, $h:expr) => (
+    Rect::new($x as i32, $y as i32, $w as u32, $h as u32)
+  )
+);
+
+//------------------------------------------------------
+// Frame
+//------------------------------------------------------
+// What one frame shows, read from the editor without changing it. The
+// focused pane's cursor is the one of the current buffer.
+pub struct Frame<'a> {
+  pub buffers: &'a BufferList,
+  // Panes and the area each takes
+  pub panes: Vec<(Pane, Area)>,
+  pub focused: usize,
+  pub mode: Mode,
+  pub wrap: bool,
+  pub highlight: Option<&'a Pattern>,
+  // Row and columns of the match :s asks about
+  pub current_match: Option<(usize, usize, usize)>,
+  // Buffer id and name shown right of the status bar
+  pub file: String,
+  // Command being typed or message, one entry per screen line
+  pub message: Vec<String>,
+}
+
+impl<'a> Frame<'a> {
+  // The editor's state with its panes laid out over `area`
+  pub fn new(editor: &'a Editor, area: Area) -> Self {
+    let panes = editor.layout.panes();
+    let buffer = editor.buffers.current();
+    let handler = &editor.handler;
+    let message = match handler.cmd_active() {
+      true  => vec![format!("{}{}", handler.prompt(), handler.command())],
+      false if editor.message.is_empty() => match handler.recording() {
+        Some(register) => vec![format!("recording @{}", register)],
+        None => Vec::new(),
+      },
+      false => editor.message.lines().map(String::from).collect(),
+    };
+    Frame {
+      buffers: &editor.buffers,
+      panes: editor
+        .layout
+        .arrange(area)
+        .into_iter()
+        .filter_map(|(id, area)| panes.iter().find(|pane| pane.id == id).map(|pane| (**pane, area)))
+        .collect(),
+      focused: editor.layout.focused().id,
+      mode: handler.mode(),
+      wrap: editor.options.wrap,
+      highlight: editor.highlight(),
+      current_match: editor.current_match(),
+      file: format!(
+        "{} {}{}",
+        editor.buffers.current_id(),
+        buffer.file.name(),
+        if buffer.is_dirty() { " [+]" } else { "" },
+      ),
+      message,
+    }
+  }
+}
+
+//------------------------------------------------------
+// Backend
+//------------------------------------------------------
+// Fonts the renderer writes with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+  Text,
+  Status,
+}
+
+// What the renderer draws on. Positions and sizes are in pixels and
+// the fonts are monospaced.
+pub trait Backend {
+  fn size(&self) -> Result<(u32, u32), String>;
+  // Width and line height of a character of `face`
+  fn cell(&self, face: Face) -> (u32, u32);
+  fn clear(&mut self, color: Color);
+  fn fill(&mut self, area: Area, color: Color) -> Result<(), String>;
+  fn line(&mut self, from: (i32, i32), to: (i32, i32), color: Color) -> Result<(), String>;
+  fn text(&mut self, x: i32, y: i32, text: &str, face: Face, color: Color) -> Result<(), String>;
+  // Keeps drawing inside `area`, or anywhere with None
+  fn clip(&mut self, area: Option<Area>);
+  fn present(&mut self);
+}
+
+// Draws to an SDL window with fonts loaded once. Text is copied glyph
+// by glyph out of an atlas.
+pub struct SdlBackend {
+  canvas: Canvas<Window>,
+  text: Font<'static, 'static>,
+  status: Font<'static, 'static>,
+  // Cell sizes of the text and status fonts
+  cells: ((u32, u32), (u32, u32)),
+  atlas: Atlas,
+}
+
+impl SdlBackend {
+  // The fonts and atlas pages borrow the TTF context and the texture
+  // creator, which live as long as the program
+  pub fn new(
+    canvas: Canvas<Window>,
+    texture_creator: &'static TextureCreator<WindowContext>,
+    ttf_context: &'static Sdl2TtfContext,
+    font: &str,
+  ) -> Result<Self, String> {
+    let mut text = ttf_context.load_font(font, FONTSIZE)?;
+    let mut status = ttf_context.load_font(font, STATUS_FONTSIZE)?;
+    text.set_style(FontStyle::NORMAL);
+    status.set_style(FontStyle::NORMAL);
+    let cell = |font: &Font| -> Result<(u32, u32), String> {
+      let (width, _) = font.size_of_char('M').map_err(|e| e.to_string())?;
+      Ok((width, font.recommended_line_spacing() as u32))
+    };
+    Ok(SdlBackend {
+      canvas,
+      cells: (cell(&text)?, cell(&status)?),
+      text,
+      status,
+      atlas: Atlas::new(texture_creator),
+    })
+  }
+}
+
+impl Backend for SdlBackend {
+  fn size(&self) -> Result<(u32, u32), String> {
+    self.canvas.output_size()
+  }
+
+  fn cell(&self, face: Face) -> (u32, u32) {
+    match face {
+      Face::Text => self.cells.0,
+      Face::Status => self.cells.1,
+    }
+  }
+
+  fn clear(&mut self, color: Color) {
+    self.canvas.set_draw_color(color);
+    self.canvas.clear();
+  }
+
+  fn fill(&mut self, area: Area, color: Color) -> Result<(), String> {
+    self.canvas.set_draw_color(color);
+    self.canvas.fill_rect(rect!(area.x, area.y, area.w, area.h))
+  }
+
+  fn line(&mut self, from: (i32, i32), to: (i32, i32), color: Color) -> Result<(), String> {
+    self.canvas.set_draw_color(color);
+    self.canvas.draw_line(from, to)
+  }
+
+  // Glyphs are stored white and tinted while copied, so colours cost
+  // no textures of their own
+  fn text(&mut self, x: i32, y: i32, text: &str, face: Face, color: Color) -> Result<(), String> {
+    let (cell_w, _) = self.cell(face);
+    let font = match face {
+      Face::Text => &self.text,
+      Face::Status => &self.status,
+    };
+    for (i, c) in text.chars().enumerate().filter(|(_, c)| !c.is_whitespace()) {
+      let Some(glyph) = self.atlas.glyph(font, face, c)? else {
+        continue
+      };
+      let page = &mut self.atlas.pages[glyph.page];
+      page.set_color_mod(color.r, color.g, color.b);
+      page.set_alpha_mod(color.a);
+      let dest = rect!(x + (i as u32 * cell_w) as i32, y, glyph.rect.width(), glyph.rect.height());
+      self.canvas.copy(page, glyph.rect, dest)?;
+    }
+    Ok(())
+  }
+
+  fn clip(&mut self, area: Option<Area>) {
+    self.canvas.set_clip_rect(area.map(|area| rect!(area.x, area.y, area.w, area.h)));
+  }
+
+  fn present(&mut self) {
+    self.canvas.present()
+  }
+}
+
+//------------------------------------------------------
+// Atlas
+//------------------------------------------------------
+// Where a glyph is kept in the atlas
+#[derive(Debug, Clone, Copy)]
+struct Glyph {
+  page: usize,
+  rect: Rect,
+}
+
+// Glyphs rendered once each and packed in rows on texture pages, a new
+// page starting when the last one is full. The face gives the size,
+// the style is always normal.
+struct Atlas {
+  texture_creator: &'static TextureCreator<WindowContext>,
+  pages: Vec<Texture<'static>>,
+  // None for glyphs the font cannot render, so they are tried once
+  glyphs: HashMap<(Face, char), Option<Glyph>>,
+  // Where the next glyph goes on the last page, and the height of the
+  // row it goes in
+  x: u32,
+  y: u32,
+  row_h: u32,
+}
+
+impl Atlas {
+  fn new(texture_creator: &'static TextureCreator<WindowContext>) -> Self {
+    Atlas {
+      texture_creator,
+      pages: Vec::new(),
+      glyphs: HashMap::new(),
+      x: 0,
+      y: 0,
+      row_h: 0,
+    }
+  }
+
+  fn glyph(&mut self, font: &Font, face: Face, c: char) -> Result<Option<Glyph>, String> {
+    if let Some(glyph) = self.glyphs.get(&(face, c)) {
+      return Ok(*glyph)
+    }
+    let glyph = self.add(font, c)?;
+    self.glyphs.insert((face, c), glyph);
+    Ok(glyph)
+  }
+
+  fn add(&mut self, font: &Font, c: char) -> Result<Option<Glyph>, String> {
+    let Ok(surface) = font.render_char(c).blended(Color::WHITE) else {
+      return Ok(None)
+    };
+    let surface = surface.convert_format(PixelFormatEnum::ARGB8888)?;
+    let (w, h) = (surface.width(), surface.height());
+    if w == 0 || h == 0 || w > PAGE_SIZE || h > PAGE_SIZE {
+      return Ok(None)
+    }
+    if self.x + w > PAGE_SIZE {
+      (self.x, self.y, self.row_h) = (0, self.y + self.row_h, 0);
+    }
+    if self.pages.is_empty() || self.y + h > PAGE_SIZE {
+      let mut page = self
+        .texture_creator
+        .create_texture_static(PixelFormatEnum::ARGB8888, PAGE_SIZE, PAGE_SIZE)
+        .map_err(|e| e.to_string())?;
+      page.set_blend_mode(BlendMode::Blend);
+      self.pages.push(page);
+      (self.x, self.y, self.row_h) = (0, 0, 0);
+    }
+    let glyph = Glyph { page: self.pages.len() - 1, rect: rect!(self.x, self.y, w, h) };
+    let page = &mut self.pages[glyph.page];
+    surface
+      .with_lock(|pixels| page.update(glyph.rect, pixels, surface.pitch() as usize))
+      .map_err(|e| e.to_string())?;
+    self.x += w;
+    self.row_h = self.row_h.max(h);
+    Ok(Some(glyph))
+  }
+}
+
+//------------------------------------------------------
+// Renderer
+//------------------------------------------------------
+pub struct Renderer<B: Backend> {
+  backend: B,
+  theme: Theme,
+}
+
+impl<B: Backend> Renderer<B> {
+  pub fn new(backend: B, theme: Theme) -> Self {
+    Renderer { backend, theme }
+  }
+
+  // Size of a character of the buffer text
+  pub fn cell(&self) -> (u32, u32) {
+    self.backend.cell(Face::Text)
+  }
+
+  // Where the panes go, everything above the status bar
+  pub fn text_area(&self) -> Result<Area, String> {
+    let size = self.backend.size()?;
+    Ok(Area::new(0, 0, size.0, size.1.saturating_sub(STATUS_LINE)))
+  }
+
+  pub fn render(&mut self, frame: &Frame) -> Result<(), String> {
+    self.backend.clear(self.theme.background);
+    for (pane, area) in &frame.panes {
+      self.render_pane(frame, pane, *area)?;
+    }
+    self.render_status_bar(frame)?;
+    self.backend.present();
+    Ok(())
+  }
+
+  fn render_status_bar(&mut self, frame: &Frame) -> Result<(), String> {
+    let size = self.backend.size()?;
+    let (char_w, _) = self.backend.cell(Face::Status);
+    let bar = Area::new(0, size.1 as i32 - STATUS_LINE as i32, size.0, STATUS_LINE);
+    self.backend.fill(bar, self.theme.status_bar)?;
+
+    // MODE
+    self.backend.text(bar.x + 10, bar.y, &frame.mode.to_string(), Face::Status, self.theme.text)?;
+
+    // FILE
+    let width = frame.file.chars().count() as u32 * char_w;
+    self.backend.text(bar.x + bar.w as i32 - width as i32 - 10, bar.y, &frame.file, Face::Status, self.theme.text)?;
+
+    // COMMAND / MESSAGE
+    // Messages longer than a line go above the bar, over the text. Only
+    // their last lines are shown when they don't fit.
+    let fit = (bar.y.max(0) as u32 / STATUS_LINE) as usize;
+    let lines = &frame.message[frame.message.len().saturating_sub(fit.max(1))..];
+    let (x, top) = match lines.len() {
+      0 | 1 => (110, bar.y),
+      n => {
+        let top = bar.y - STATUS_LINE as i32 * n as i32;
+        self.backend.fill(Area::new(0, top, size.0, (bar.y - top) as u32), self.theme.status_bar)?;
+        (bar.x + 10, top)
+      }
+    };
+    for (i, line) in lines.iter().enumerate() {
+      if line.is_empty() {
+        continue
+      }
+      self.backend.text(x, top + STATUS_LINE as i32 * i as i32, line, Face::Status, self.theme.text)?;
+    }
+
+    Ok(())
+  }
+
+  fn render_pane(&mut self, frame: &Frame, pane: &Pane, area: Area) -> Result<(), String> {
+    let Some(buffer) = frame.buffers.get(pane.buffer) else {
+      return Ok(())
+    };
+    self.backend.clip(Some(area));
+    if let Some(pattern) = frame.highlight {
+      self.render_matches(frame, buffer, pane, area, pattern)?;
+    }
+    if pane.id == frame.focused {
+      if let Some((row, from, to)) = frame.current_match {
+        let visible = self.visible_rows(frame, buffer, pane, area);
+        if let Some((_, y)) = visible.into_iter().find(|(visible, _)| *visible == row) {
+          self.fill_cols(frame, pane, area, y, (from, to), self.theme.current_match)?;
+        }
+      }
+      if let Mode::Visual(visual) = frame.mode {
+        self.render_selection(frame, buffer, pane, area, visual)?;
+      }
+      self.render_cursor(frame, buffer, pane, area)?;
+    }
+    self.render_txt_buffer(frame, buffer, pane, area)?;
+
+    if area.x > 0 {
+      self.backend.line((area.x, area.y), (area.x, area.y + area.h as i32), self.theme.status_bar)?;
+    }
+    if area.y > 0 {
+      self.backend.line((area.x, area.y), (area.x + area.w as i32, area.y), self.theme.status_bar)?;
+    }
+    self.backend.clip(None);
+    Ok(())
+  }
+
+  fn render_cursor(&mut self, frame: &Frame, buffer: &Buffer, pane: &Pane, area: Area) -> Result<(), String> {
+    let (cell_w, cell_h) = self.cell();
+    let cols = (area.w / cell_w.max(1)).max(1) as usize;
+    let (row, col) = (buffer.row(), buffer.col());
+    // On a closed fold the cursor sits at the start of its line
+    let col = match buffer.fold_at(row) {
+      Some(_) => pane.left,
+      None => col,
+    };
+    let (x, y) = match frame.wrap {
+      true  => {
+        let above: usize = (pane.top..row).map(|i| buffer.display_height(i, cols)).sum();
+        (col % cols, above + col / cols)
+      },
+      false => {
+        let above: usize = (pane.top..row).map(|i| buffer.display_height(i, usize::MAX)).sum();
+        (col.saturating_sub(pane.left), above)
+      },
+    };
+    let width = match frame.mode {
+      Mode::Insert => 3,
+      _ => cell_w,
+    };
+    let cursor = Area::new(
+      area.x + (x as u32 * cell_w) as i32,
+      area.y + (y as u32 * cell_h) as i32,
+      width,
+      cell_h,
+    );
+    self.backend.fill(cursor, self.theme.cursor)
+  }
+
+  fn render_selection(&mut self, frame: &Frame, buffer: &Buffer, pane: &Pane, area: Area, visual: Visual) -> Result<(), String> {
+    for (row, y) in self.visible_rows(frame, buffer, pane, area) {
+      if let Some(cols) = buffer.selected_cols(row, visual) {
+        self.fill_cols(frame, pane, area, y, cols, self.theme.selection)?;
+      }
+    }
+    Ok(())
+  }
+
+  fn render_matches(&mut self, frame: &Frame, buffer: &Buffer, pane: &Pane, area: Area, pattern: &Pattern) -> Result<(), String> {
+    for (row, y) in self.visible_rows(frame, buffer, pane, area) {
+      for cols in buffer.matches(row, pattern) {
+        self.fill_cols(frame, pane, area, y, cols, self.theme.matched)?;
+      }
+    }
+    Ok(())
+  }
+
+  // Rows of the pane's buffer on screen and the screen line each starts on
+  fn visible_rows(&self, frame: &Frame, buffer: &Buffer, pane: &Pane, area: Area) -> Vec<(usize, usize)> {
+    let (cell_w, cell_h) = self.cell();
+    let cols = (area.w / cell_w.max(1)).max(1) as usize;
+    let rows = (area.h / cell_h.max(1)) as usize + 1;
+    let mut visible = Vec::new();
+    let mut y = 0;
+    for row in pane.top..buffer.height() {
+      if y > rows {
+        break
+      }
+      let height = match frame.wrap {
+        true  => buffer.display_height(row, cols),
+        false => buffer.display_height(row, usize::MAX),
+      };
+      if height > 0 {
+        visible.push((row, y));
+      }
+      y += height;
+    }
+    visible
+  }
+
+  // Fills columns from..to of the row starting `y` lines down the pane.
+  // Wrapped rows are split at the pane's width like in render_txt_buffer.
+  fn fill_cols(&mut self, frame: &Frame, pane: &Pane, area: Area, y: usize, (from, to): (usize, usize), color: Color) -> Result<(), String> {
+    let (cell_w, cell_h) = self.cell();
+    let cols = (area.w / cell_w.max(1)).max(1) as usize;
+    // (screen line, first and last column) of each highlighted stretch
+    let spans: Vec<(usize, usize, usize)> = match frame.wrap {
+      true  => (from / cols..=to.saturating_sub(1) / cols)
+        .map(|sub| (y + sub, from.max(sub * cols) - sub * cols, to.min((sub + 1) * cols) - sub * cols))
+        .collect(),
+      false => vec![(y, from.saturating_sub(pane.left), to.saturating_sub(pane.left).min(cols + 1))],
+    };
+    for (line, from, to) in spans.into_iter().filter(|span| span.2 > span.1) {
+      self.backend.fill(Area::new(
+        area.x + (from as u32 * cell_w) as i32,
+        area.y + (line as u32 * cell_h) as i32,
+        (to - from) as u32 * cell_w,
+        cell_h
+      ), color)?;
+    }
+    Ok(())
+  }
+
+  // Wrapped rows are cut into segments of the pane's width, otherwise
+  // only the columns right of the horizontal scroll are drawn. Each
+  // highlighted span of a segment is drawn in its theme colour. A closed
+  // fold is drawn as one line telling how many rows it hides.
+  fn render_txt_buffer(&mut self, frame: &Frame, buffer: &Buffer, pane: &Pane, area: Area) -> Result<(), String> {
+    if buffer.is_empty() {
+      return Ok(())
+    }
+    let (cell_w, cell_h) = self.cell();
+    let cols = (area.w / cell_w.max(1)).max(1) as usize;
+    let mut vert_offset = 0;
+    for i in pane.top..buffer.height() {
+      if vert_offset > area.h {
+        break
+      }
+      let (line, spans): (Vec<char>, Vec<(Span, Color)>) = match buffer.fold_at(i) {
+        Some((first, _)) if first < i => continue,
+        Some((first, last)) => {
+          let text = format!("+--{:>3} lines: {}", last - first + 1, buffer.nth(i).trim());
+          let line: Vec<char> = text.chars().collect();
+          let span = Span { from: 0, to: line.len(), class: Class::Text };
+          (line, vec![(span, self.theme.fold)])
+        },
+        None => {
+          let line: Vec<char> = buffer.nth(i).chars().collect();
+          let spans = match &buffer.spans(i)[..] {
+            [] => vec![Span { from: 0, to: line.len(), class: Class::Text }],
+            spans => spans.to_vec(),
+          };
+          (line, spans.into_iter().map(|span| (span, self.theme.color(span.class))).collect())
+        },
+      };
+      // First and last column of each segment. A fold line is never
+      // wrapped or scrolled, it takes one screen line.
+      let segments: Vec<(usize, usize)> = match frame.wrap {
+        _ if buffer.fold_at(i).is_some() => vec![(0, line.len().min(cols))],
+        true if !line.is_empty() => (0..line.len()).step_by(cols).map(|from| (from, (from + cols).min(line.len()))).collect(),
+        true => vec![(0, 0)],
+        false => vec![(pane.left.min(line.len()), (pane.left + cols + 1).min(line.len()))],
+      };
+      for (start, end) in segments {
+        for (span, color) in &spans {
+          let (from, to) = (span.from.max(start), span.to.min(end));
+          if from >= to || line[from..to].iter().all(|c| c.is_whitespace()) {
+            continue
+          }
+          let text = String::from_iter(&line[from..to]);
+          let x = area.x + ((from - start) as u32 * cell_w) as i32;
+          self.backend.text(x, area.y + vert_offset as i32, &text, Face::Text, *color)?;
+        }
+        vert_offset += cell_h;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // What the renderer asked for, in order
+  #[derive(Debug, Clone, PartialEq, Eq)]
+  enum Op {
+    Fill(Area, Color),
+    Text(i32, i32, String, Face, Color),
+  }
+
+  // Records fills and text on a 200x120 window with 10x20 cells
+  #[derive(Default)]
+  struct Recorder {
+    ops: Vec<Op>,
+  }
+
+  impl Backend for Recorder {
+    fn size(&self) -> Result<(u32, u32), String> {
+      Ok((200, 120))
+    }
+
+    fn cell(&self, _: Face) -> (u32, u32) {
+      (10, 20)
+    }
+
+    fn clear(&mut self, _: Color) {
+      self.ops.clear()
+    }
+
+    fn fill(&mut self, area: Area, color: Color) -> Result<(), String> {
+      self.ops.push(Op::Fill(area, color));
+      Ok(())
+    }
+
+    fn line(&mut self, _: (i32, i32), _: (i32, i32), _: Color) -> Result<(), String> {
+      Ok(())
+    }
+
+    fn text(&mut self, x: i32, y: i32, text: &str, face: Face, color: Color) -> Result<(), String> {
+      self.ops.push(Op::Text(x, y, text.to_string(), face, color));
+      Ok(())
+    }
+
+    fn clip(&mut self, _: Option<Area>) {}
+
+    fn present(&mut self) {}
+  }
+
+  fn editor(text: &str, keys: &str) -> Editor {
+    let area = Area::new(0, 0, 200, 100);
+    let mut editor = Editor::new(Buffer::new(text), area, (10, 20), None);
+    for key in keys.chars() {
+      editor.feed(key);
+    }
+    editor
+  }
+
+  fn render(editor: &Editor) -> Vec<Op> {
+    let mut renderer = Renderer::new(Recorder::default(), Theme::default());
+    let area = renderer.text_area().unwrap();
+    renderer.render(&Frame::new(editor, area)).unwrap();
+    renderer.backend.ops
+  }
+
+  fn text(x: i32, y: i32, text: &str, color: Color) -> Op {
+    Op::Text(x, y, text.to_string(), Face::Text, color)
+  }
+
+  #[test]
+  fn cursor() {
+    let theme = Theme::default();
+    let ops = render(&editor("abc\ndef", "jl"));
+    assert!(ops.contains(&Op::Fill(Area::new(10, 20, 10, 20), theme.cursor)));
+    // A bar in Insert mode
+    let ops = render(&editor("abc\ndef", "jli"));
+    assert!(ops.contains(&Op::Fill(Area::new(10, 20, 3, 20), theme.cursor)));
+  }
+
+  #[test]
+  fn selection() {
+    let theme = Theme::default();
+    let ops = render(&editor("abcdef\nghi", "lvlj"));
+    // The selection takes in the line break of the rows it goes past
+    assert!(ops.contains(&Op::Fill(Area::new(10, 0, 60, 20), theme.selection)));
+    assert!(ops.contains(&Op::Fill(Area::new(0, 20, 30, 20), theme.selection)));
+    let ops = render(&editor("abcdef\nghi", "Vj"));
+    assert!(ops.contains(&Op::Fill(Area::new(0, 0, 70, 20), theme.selection)));
+  }
+
+  #[test]
+  fn wrapped_rows() {
+    let theme = Theme::default();
+    let line = "abcdefghijklmnopqrstuvwxy";
+    let ops = render(&editor(&format!("{}\nz", line), ""));
+    assert!(ops.contains(&text(0, 0, &line[..20], theme.text)));
+    assert!(ops.contains(&text(0, 20, &line[20..], theme.text)));
+    assert!(ops.contains(&text(0, 40, "z", theme.text)));
+    // Without wrapping the row is cut after the column the pane's width
+    // ends in
+    let ops = render(&editor(&format!("{}\nz", line), ":set nowrap\r"));
+    assert!(ops.contains(&text(0, 0, &line[..21], theme.text)));
+    assert!(ops.contains(&text(0, 20, "z", theme.text)));
+  }
+
+  #[test]
+  #[cfg(feature = "tree-sitter")]
+  fn folds() {
+    use crate::buffer::syntax::Language;
+    let theme = Theme::default();
+    let mut editor = editor("fn f() {\n  x\n}\nnext", "");
+    editor.buffers.current_mut().set_language(Language::Rust);
+    for key in "jzc".chars() {
+      editor.feed(key);
+    }
+    let ops = render(&editor);
+    assert!(ops.contains(&text(0, 0, "+--  3 lines: fn f()", theme.fold)));
+    assert!(ops.contains(&text(0, 20, "next", theme.text)));
+    assert!(ops.contains(&Op::Fill(Area::new(0, 0, 10, 20), theme.cursor)));
+  }
+
+  #[test]
+  fn status_messages() {
+    let theme = Theme::default();
+    let mut editor = editor("abc", "");
+    editor.message = "one\ntwo\nthree".to_string();
+    let ops = render(&editor);
+    let status = |x, y, text: &str| Op::Text(x, y, text.to_string(), Face::Status, theme.text);
+    // Three lines above the bar are taken over for the message, the bar
+    // keeps the mode
+    assert!(ops.contains(&Op::Fill(Area::new(0, 40, 200, 60), theme.status_bar)));
+    assert!(ops.contains(&status(10, 40, "one")));
+    assert!(ops.contains(&status(10, 60, "two")));
+    assert!(ops.contains(&status(10, 80, "three")));
+    let on_bar: Vec<&str> = ops
+      .iter()
+      .filter_map(|op| match op {
+        Op::Text(_, 100, text, ..) => Some(text.as_str()),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(on_bar, ["Edit", "1 [No Name]"]);
+    // Lines that don't fit above the bar are dropped from the top
+    editor.message = (0..20).map(|i| i.to_string()).collect::<Vec<_>>().join("\n");
+    let ops = render(&editor);
+    assert!(ops.contains(&Op::Fill(Area::new(0, 0, 200, 100), theme.status_bar)));
+    assert!(ops.contains(&status(10, 0, "15")));
+    assert!(ops.contains(&status(10, 80, "19")));
+    assert!(!ops.iter().any(|op| matches!(op, Op::Text(_, _, text, ..) if text == "14")));
+    // A one line message sits right of the mode
+    editor.message = "hello".to_string();
+    let ops = render(&editor);
+    assert!(ops.contains(&status(110, 100, "hello")));
+  }
+}
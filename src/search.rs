@@ -0,0 +1,247 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Search
+//------------------------------------------------------
+//------------------------------------------------------
+use std::ops::Range;
+use regex::{Regex, RegexBuilder};
+
+// Syntax patterns are written in, chosen with `:set regex=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+  Vim,
+  Rust,
+}
+
+// A compiled pattern for /, ? and :s. ^ and $ match at every line break,
+// as searches run over the whole buffer.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+  regex: Regex,
+  // Whether a match can run over a line break
+  spans_lines: bool,
+}
+
+impl Pattern {
+  pub fn new(pattern: &str, syntax: Syntax, ignore_case: bool) -> Result<Self, String> {
+    if pattern.is_empty() {
+      return Err("Empty pattern".to_string())
+    }
+    let (source, ignore) = match syntax {
+      Syntax::Vim => translate(pattern)?,
+      Syntax::Rust => (pattern.to_string(), false),
+    };
+    let regex = RegexBuilder::new(&source)
+      .multi_line(true)
+      .case_insensitive(ignore_case || ignore)
+      .build()
+      .map_err(|_| format!("Invalid pattern: {}", pattern))?;
+    let spans_lines = spans_lines(&source);
+    Ok(Pattern { regex, spans_lines })
+  }
+
+  pub fn regex(&self) -> &Regex {
+    &self.regex
+  }
+
+  pub fn spans_lines(&self) -> bool {
+    self.spans_lines
+  }
+
+  // Byte ranges of the matches in `text`
+  pub fn find_all(&self, text: &str) -> Vec<Range<usize>> {
+    self.regex
+      .find_iter(text)
+      .map(|m| m.range())
+      .collect()
+  }
+}
+
+// Pattern matching `word` as a whole word, for * and #. Rust syntax
+// escapes with regex::escape, vim syntax only escapes what is magic
+// there, as a backslash would turn + ? ( and | into operators.
+pub fn whole_word(word: &str, syntax: Syntax) -> String {
+  match syntax {
+    Syntax::Rust => format!(r"\b{{start}}{}\b{{end}}", regex::escape(word)),
+    Syntax::Vim => {
+      let escaped: String = word
+        .chars()
+        .flat_map(|c| match c {
+          '\\' | '.' | '*' | '[' | '~' | '^' | '$' | '/' => vec!['\\', c],
+          c => vec![c],
+        })
+        .collect();
+      format!(r"\<{}\>", escaped)
+    },
+  }
+}
+
+// Whether the regex `source` could match a "\n", erring on the side of
+// true: escapes and classes that can stand for one, and negated classes
+fn spans_lines(source: &str) -> bool {
+  source.contains('\n')
+    || [r"\n", r"\s", r"\S", r"\D", r"\W", r"\H", r"\x", r"\u", r"\U", r"\p", r"\P", "[^", "[[:", "(?"]
+      .iter()
+      .any(|escape| source.contains(escape))
+}
+
+// Rust regex syntax for a vim pattern, which is read as with 'magic' set:
+// ( ) | + ? { } are literal unless escaped and \< \> mark word ends.
+// Returns true as well when \c asks to ignore case.
+fn translate(pattern: &str) -> Result<(String, bool), String> {
+  let mut out = String::new();
+  let mut ignore_case = false;
+  // ^ is an anchor at the start of the pattern or a branch
+  let mut at_start = true;
+  let mut chars = pattern.chars().peekable();
+  while let Some(c) = chars.next() {
+    let start = std::mem::replace(&mut at_start, false);
+    match c {
+      '\\' => match chars.next() {
+        Some('(') => {
+          out.push('(');
+          at_start = true;
+        },
+        Some('|') => {
+          out.push('|');
+          at_start = true;
+        },
+        Some(')') => out.push(')'),
+        Some('+') => out.push('+'),
+        Some('=' | '?') => out.push('?'),
+        Some('{') => {
+          let mut count = String::new();
+          for c in chars.by_ref() {
+            if c == '}' {
+              break
+            }
+            count.push(c);
+          }
+          let count = count.trim_end_matches('\\');
+          let (lazy, count) = match count.strip_prefix('-') {
+            Some(count) => ("?", count),
+            None => ("", count),
+          };
+          match count {
+            "" => out.push('*'),
+            _ if count.starts_with(',') => out.push_str(&format!("{{0{}}}", count)),
+            _ => out.push_str(&format!("{{{}}}", count)),
+          }
+          out.push_str(lazy);
+        },
+        Some('<') => out.push_str(r"\b{start}"),
+        Some('>') => out.push_str(r"\b{end}"),
+        Some(c @ ('s' | 'S' | 'd' | 'D' | 'w' | 'W' | 'n' | 't' | 'r')) => {
+          out.push('\\');
+          out.push(c);
+        },
+        Some('e') => out.push_str(r"\x1B"),
+        Some('a') => out.push_str("[a-zA-Z]"),
+        Some('A') => out.push_str("[^a-zA-Z]"),
+        Some('l') => out.push_str("[a-z]"),
+        Some('L') => out.push_str("[^a-z]"),
+        Some('u') => out.push_str("[A-Z]"),
+        Some('U') => out.push_str("[^A-Z]"),
+        Some('x') => out.push_str("[0-9A-Fa-f]"),
+        Some('X') => out.push_str("[^0-9A-Fa-f]"),
+        Some('o') => out.push_str("[0-7]"),
+        Some('O') => out.push_str("[^0-7]"),
+        Some('h') => out.push_str("[A-Za-z_]"),
+        Some('H') => out.push_str("[^A-Za-z_]"),
+        Some('c') => ignore_case = true,
+        Some('C') => {},
+        Some(c @ '1'..='9') => return Err(format!("Backreferences are not supported: \\{}", c)),
+        Some(c) if c.is_alphanumeric() || "%@".contains(c) => return Err(format!("Unsupported: \\{}", c)),
+        Some(c) => out.push_str(&regex::escape(&c.to_string())),
+        None => out.push_str(r"\\"),
+      },
+      '[' => match class(&mut chars) {
+        Some(class) => out.push_str(&class),
+        None => out.push_str(r"\["),
+      },
+      '^' if start => out.push('^'),
+      '$' if chars.peek().is_none() || matches!(chars.clone().take(2).collect::<String>().as_str(), r"\|" | r"\)") => {
+        out.push('$')
+      },
+      '*' | '.' => out.push(c),
+      c => out.push_str(&regex::escape(&c.to_string())),
+    }
+  }
+  Ok((out, ignore_case))
+}
+
+// A [] collection after its [, None when it has no closing ]
+fn class(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+  let mut rest = chars.clone();
+  let mut class = String::from("[");
+  if rest.peek() == Some(&'^') {
+    class.push(rest.next()?);
+  }
+  // A ] right at the start belongs to the collection
+  if rest.peek() == Some(&']') {
+    rest.next();
+    class.push_str(r"\]");
+  }
+  loop {
+    match rest.next()? {
+      ']' => break,
+      '\\' => {
+        class.push('\\');
+        class.push(rest.next()?);
+      },
+      c @ ('[' | '&' | '~') => {
+        class.push('\\');
+        class.push(c);
+      },
+      c => class.push(c),
+    }
+  }
+  class.push(']');
+  *chars = rest;
+  Some(class)
+}
+
+// Patterns searched for, oldest first, and what n and N repeat
+#[derive(Default)]
+pub struct Search {
+  history: Vec<String>,
+  // Entry shown while walking the history with Up and Down
+  browsing: Option<usize>,
+  // Last pattern and whether it was searched forwards
+  pub last: Option<(String, bool)>,
+  pub pattern: Option<Pattern>,
+  // Matches of the last pattern are highlighted until :nohlsearch
+  pub highlight: bool,
+}
+
+impl Search {
+  pub fn new() -> Self {
+    Search::default()
+  }
+
+  pub fn push(&mut self, pattern: &str, forward: bool, compiled: Pattern) {
+    self.history.retain(|old| old != pattern);
+    self.history.push(pattern.to_string());
+    self.browsing = None;
+    self.last = Some((pattern.to_string(), forward));
+    self.pattern = Some(compiled);
+    self.highlight = true;
+  }
+
+  // Up and Down on the search line, stepping past the newest entry gives
+  // back an empty line
+  pub fn browse(&mut self, step: isize) -> Option<&str> {
+    if self.history.is_empty() {
+      return None
+    }
+    let len = self.history.len();
+    let at = self.browsing.unwrap_or(len) as isize + step;
+    let at = at.clamp(0, len as isize) as usize;
+    self.browsing = Some(at);
+    Some(self.history.get(at).map_or("", |pattern| pattern.as_str()))
+  }
+
+  pub fn stop_browsing(&mut self) {
+    self.browsing = None;
+  }
+}
@@ -0,0 +1,312 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Substitute
+//------------------------------------------------------
+//------------------------------------------------------
+use std::ops::Range;
+use regex::Captures;
+use crate::buffer::buffer::Buffer;
+use crate::search::{Pattern, Syntax};
+
+// :s/pattern/replacement/flags [count]. In the replacement & is the whole
+// match, \0 to \9 are groups, \u and \l change the case of the next char,
+// \U and \L the ones up to \E, and \r breaks the line. With the Rust
+// syntax $1, $name and ${name} name groups as well.
+pub struct Substitute {
+  // Pattern as typed, for the search history
+  pub pattern: String,
+  compiled: Pattern,
+  replacement: String,
+  syntax: Syntax,
+  // g: every match in a row instead of the first one
+  global: bool,
+  // c: asks before each replacement
+  pub confirm: bool,
+  // n: counts the matches without replacing
+  pub count_only: bool,
+  // e: finding nothing is no error
+  pub quiet: bool,
+  // Rows to work on, starting from the last one of the range
+  pub count: Option<usize>,
+}
+
+impl Substitute {
+  // `args` follows :s, an empty pattern stands for the last search
+  pub fn parse(args: &str, last_search: Option<&str>, syntax: Syntax) -> Result<Self, String> {
+    let mut chars = args.chars();
+    let delimiter = match chars.next() {
+      Some(c) if !c.is_alphanumeric() && !"\\\"| ".contains(c) => c,
+      _ => return Err(format!("Invalid delimiter: {}", args)),
+    };
+    let (pattern, rest) = split(chars.as_str(), delimiter);
+    let (replacement, flags) = split(rest.unwrap_or(""), delimiter);
+    let pattern = match pattern.is_empty() {
+      true  => last_search.ok_or("No previous regular expression")?.to_string(),
+      false => pattern,
+    };
+    let flags = flags.unwrap_or("").trim();
+    let letters = flags.find(|c: char| !c.is_ascii_alphabetic() && c != '&').unwrap_or(flags.len());
+    let count = match flags[letters..].trim() {
+      "" => None,
+      count => Some(count
+        .parse::<usize>()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or(format!("Trailing characters: {}", count))?),
+    };
+    let (mut global, mut confirm, mut count_only, mut quiet, mut ignore_case) = (false, false, false, false, false);
+    for flag in flags[..letters].chars() {
+      match flag {
+        'g' => global = !global,
+        'c' => confirm = true,
+        'n' => count_only = true,
+        'e' => quiet = true,
+        'i' => ignore_case = true,
+        'I' => ignore_case = false,
+        '&' => {},
+        _ => return Err(format!("Trailing characters: {}", flag)),
+      }
+    }
+    Ok(Substitute {
+      compiled: Pattern::new(&pattern, syntax, ignore_case)?,
+      pattern,
+      replacement,
+      syntax,
+      global,
+      confirm,
+      count_only,
+      quiet,
+      count,
+    })
+  }
+
+  pub fn replacement_text(&self) -> &str {
+    &self.replacement
+  }
+
+  pub fn compiled(&self) -> &Pattern {
+    &self.compiled
+  }
+
+  // The match in `line` at or after byte `from`
+  pub fn find(&self, line: &str, from: usize) -> Option<Range<usize>> {
+    self.compiled.regex().find_at(line, from).map(|m| m.range())
+  }
+
+  // `line` with its matches replaced, only the first one without g, and
+  // the number of replacements
+  pub fn apply(&self, line: &str) -> (String, usize) {
+    let mut out = String::new();
+    let mut last = 0;
+    let mut n = 0;
+    for caps in self.compiled.regex().captures_iter(line) {
+      let m = caps.get(0).unwrap();
+      out.push_str(&line[last..m.start()]);
+      out.push_str(&self.expand(&caps));
+      last = m.end();
+      n += 1;
+      if !self.global {
+        break
+      }
+    }
+    out.push_str(&line[last..]);
+    (out, n)
+  }
+
+  // What replaces the match at `m` in `line`
+  pub fn replacement(&self, line: &str, m: &Range<usize>) -> String {
+    self.compiled
+      .regex()
+      .captures_at(line, m.start)
+      .map_or(String::new(), |caps| self.expand(&caps))
+  }
+
+  fn expand(&self, caps: &Captures) -> String {
+    let mut out = String::new();
+    // \u or \l for the next char, \U or \L until \E
+    let mut once: Option<bool> = None;
+    let mut lasting: Option<bool> = None;
+    let group = |n: usize| caps.get(n).map_or("", |m| m.as_str());
+    let mut chars = self.replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+      let text = match c {
+        '&' => group(0).to_string(),
+        '\\' => match chars.next() {
+          Some(n @ '0'..='9') => group(n as usize - '0' as usize).to_string(),
+          Some('u') => {
+            once = Some(true);
+            continue
+          },
+          Some('l') => {
+            once = Some(false);
+            continue
+          },
+          Some('U') => {
+            lasting = Some(true);
+            continue
+          },
+          Some('L') => {
+            lasting = Some(false);
+            continue
+          },
+          Some('E' | 'e') => {
+            lasting = None;
+            continue
+          },
+          Some('r' | 'n') => "\n".to_string(),
+          Some('t') => "\t".to_string(),
+          Some(c) => c.to_string(),
+          None => "\\".to_string(),
+        },
+        '$' if self.syntax == Syntax::Rust && chars.peek().is_some_and(|c| *c == '{' || c.is_alphanumeric() || *c == '_') => {
+          let name: String = match chars.peek() {
+            Some('{') => {
+              chars.next();
+              chars.by_ref().take_while(|c| *c != '}').collect()
+            },
+            _ => std::iter::from_fn(|| chars.next_if(|c| c.is_alphanumeric() || *c == '_')).collect(),
+          };
+          match name.parse::<usize>() {
+            Ok(n) => group(n).to_string(),
+            Err(_) => caps.name(&name).map_or("", |m| m.as_str()).to_string(),
+          }
+        },
+        c => c.to_string(),
+      };
+      for c in text.chars() {
+        let upper = match once.take() {
+          Some(upper) => Some(upper),
+          None => lasting,
+        };
+        match upper {
+          Some(true) => out.extend(c.to_uppercase()),
+          Some(false) => out.extend(c.to_lowercase()),
+          None => out.push(c),
+        }
+      }
+    }
+    out
+  }
+}
+
+// Text up to the first `delimiter` not escaped with \, and what follows
+// that delimiter. The \ of an escaped delimiter is dropped.
+fn split(text: &str, delimiter: char) -> (String, Option<&str>) {
+  let mut part = String::new();
+  let mut chars = text.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '\\' => match chars.next() {
+        Some((_, c)) if c == delimiter => part.push(c),
+        Some((_, c)) => {
+          part.push('\\');
+          part.push(c);
+        },
+        None => part.push('\\'),
+      },
+      c if c == delimiter => return (part, Some(&text[i + c.len_utf8()..])),
+      c => part.push(c),
+    }
+  }
+  (part, None)
+}
+
+// A :s with the c flag going through its matches as they are answered
+pub struct Confirm {
+  pub sub: Substitute,
+  // Row and byte offset the next match is looked for from, and the last
+  // row of the range
+  row: usize,
+  offset: usize,
+  last: usize,
+  // The match waiting for an answer: row and byte range in it
+  pub current: Option<(usize, Range<usize>)>,
+  pub done: usize,
+  pub lines: usize,
+  last_changed: Option<usize>,
+}
+
+impl Confirm {
+  pub fn new(sub: Substitute, first: usize, last: usize) -> Self {
+    Confirm {
+      sub,
+      row: first,
+      offset: 0,
+      last,
+      current: None,
+      done: 0,
+      lines: 0,
+      last_changed: None,
+    }
+  }
+
+  // Moves on to the next match, false when the range has no more
+  pub fn next(&mut self, buffer: &Buffer) -> bool {
+    let last = self.last.min(buffer.height() - 1);
+    while self.row <= last {
+      let line = buffer.nth(self.row);
+      if let Some(m) = self.sub.find(&line, self.offset).filter(|_| self.offset <= line.len()) {
+        self.current = Some((self.row, m));
+        return true
+      }
+      self.row += 1;
+      self.offset = 0;
+    }
+    self.current = None;
+    false
+  }
+
+  // Replaces the current match, the rows after it move down by the line
+  // breaks the replacement brings
+  pub fn replace(&mut self, buffer: &mut Buffer) {
+    let Some((row, m)) = self.current.clone() else {
+      return
+    };
+    let line = buffer.nth(row).to_string();
+    let text = self.sub.replacement(&line, &m);
+    let start = buffer.content.line_to_char(row);
+    let from = start + line[..m.start].chars().count();
+    let to = start + line[..m.end].chars().count();
+    buffer.replace(from, to, &text);
+    self.done += 1;
+    if self.last_changed != Some(row) {
+      self.lines += 1;
+    }
+    let breaks = text.matches('\n').count();
+    self.row += breaks;
+    self.last += breaks;
+    self.last_changed = Some(self.row);
+    self.offset = match text.rfind('\n') {
+      Some(i) => text.len() - i - 1,
+      None => m.start + text.len(),
+    };
+    self.advance(&line[m.clone()], &line[m.end..]);
+  }
+
+  // Leaves the current match as it is
+  pub fn skip(&mut self, buffer: &Buffer) {
+    let Some((_, m)) = self.current.clone() else {
+      return
+    };
+    let line = buffer.nth(self.row).to_string();
+    self.offset = m.end;
+    self.advance(&line[m.clone()], &line[m.end..]);
+  }
+
+  // Ends the substitution, the next call to next finds nothing
+  pub fn stop(&mut self) {
+    self.row = self.last + 1;
+    self.current = None;
+  }
+
+  // Without g the row is done after one match. An empty match steps over
+  // the char after it so it is not found again.
+  fn advance(&mut self, matched: &str, after: &str) {
+    if !self.sub.global {
+      self.row += 1;
+      self.offset = 0;
+    } else if matched.is_empty() {
+      self.offset += after.chars().next().map_or(1, |c| c.len_utf8());
+    }
+  }
+}
@@ -0,0 +1,65 @@
+//------------------------------------------------------
+//------------------------------------------------------
+// Theme
+//------------------------------------------------------
+//------------------------------------------------------
+use sdl2::pixels::Color;
+use crate::buffer::syntax::Class;
+
+// Colours of the highlight classes and of the rest of the window
+pub struct Theme {
+  pub text: Color,
+  pub keyword: Color,
+  pub types: Color,
+  pub string: Color,
+  pub comment: Color,
+  pub number: Color,
+  pub operator: Color,
+  pub heading: Color,
+  // Closed fold lines
+  pub fold: Color,
+  pub background: Color,
+  pub status_bar: Color,
+  pub cursor: Color,
+  pub selection: Color,
+  pub matched: Color,
+  // The match :s asks about
+  pub current_match: Color,
+}
+
+impl Default for Theme {
+  fn default() -> Self {
+    Theme {
+      text: Color::RGB(255, 255, 255),
+      keyword: Color::RGB(198, 120, 221),
+      types: Color::RGB(229, 192, 123),
+      string: Color::RGB(152, 195, 121),
+      comment: Color::RGB(110, 118, 129),
+      number: Color::RGB(209, 154, 102),
+      operator: Color::RGB(86, 182, 194),
+      heading: Color::RGB(97, 175, 239),
+      fold: Color::RGB(140, 150, 165),
+      background: Color::RGB(25, 25, 25),
+      status_bar: Color::RGB(60, 60, 60),
+      cursor: Color::RGB(180, 180, 180),
+      selection: Color::RGB(70, 70, 110),
+      matched: Color::RGB(110, 90, 40),
+      current_match: Color::RGB(170, 120, 30),
+    }
+  }
+}
+
+impl Theme {
+  pub fn color(&self, class: Class) -> Color {
+    match class {
+      Class::Text => self.text,
+      Class::Keyword => self.keyword,
+      Class::Type => self.types,
+      Class::String => self.string,
+      Class::Comment => self.comment,
+      Class::Number => self.number,
+      Class::Operator => self.operator,
+      Class::Heading => self.heading,
+    }
+  }
+}